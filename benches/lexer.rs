@@ -0,0 +1,70 @@
+//! Measures lexing throughput on identifier-heavy input. Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use lexer::Lexer;
+
+const ITERATIONS: u32 = 50;
+
+fn bench(name: &str, source: &str) {
+    let tokens = Lexer::new(source.as_bytes()).count();
+
+    let mut best = Duration::MAX;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        black_box(Lexer::new(black_box(source.as_bytes())).count());
+        best = best.min(start.elapsed());
+    }
+
+    println!(
+        "{:<24} {:>8} tokens  {:>10.2?}  {:>6.2} ns/token",
+        name,
+        tokens,
+        best,
+        best.as_nanos() as f64 / tokens as f64
+    );
+}
+
+fn repeat(words: &[&str], times: usize) -> String {
+    let mut source = String::new();
+    for _ in 0..times {
+        for word in words {
+            source.push_str(word);
+            source.push(' ');
+        }
+    }
+    source
+}
+
+fn main() {
+    const TIMES: usize = 20_000;
+
+    bench(
+        "identifiers",
+        &repeat(
+            &[
+                "count", "x", "total", "index", "letter", "formula", "iterator", "types",
+            ],
+            TIMES,
+        ),
+    );
+    bench(
+        "keywords",
+        &repeat(
+            &[
+                "let", "mut", "fn", "if", "else", "while", "return", "const", "match",
+            ],
+            TIMES,
+        ),
+    );
+    bench(
+        "mixed",
+        &repeat(
+            &[
+                "fn", "add", "(", "x", ":", "int", ")", "->", "int", "{", "x", "+", "1", "}",
+            ],
+            TIMES,
+        ),
+    );
+}
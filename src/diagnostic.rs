@@ -0,0 +1,324 @@
+use std::fmt::Write;
+
+use crate::{LexError, SourceMap, Span};
+
+/// A lexing error collected while lexing in recovery mode, along with the bytes it covers.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Diagnostic {
+    pub error: LexError,
+    pub span: Span,
+}
+
+/// A span of source annotated with a message. The primary label points at the error itself,
+/// secondary labels point at related code.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+impl Diagnostic {
+    pub fn message(&self) -> String {
+        match self.error {
+            LexError::UnknownCharacter { character, .. } => {
+                format!("unknown character {:?}", character)
+            }
+            LexError::UnexpectedClose {
+                bracket,
+                open: Some(_),
+                ..
+            } => format!("mismatched close bracket `{}`", bracket),
+            LexError::UnexpectedClose { bracket, .. } => {
+                format!("unexpected close bracket `{}`", bracket)
+            }
+            LexError::UnclosedOpen { bracket, .. } => format!("unclosed bracket `{}`", bracket),
+            LexError::InvalidUtf8 { .. } => "invalid UTF-8".to_string(),
+            LexError::UnterminatedString { .. } => "unterminated string".to_string(),
+            LexError::UnterminatedRawString { .. } => "unterminated raw string".to_string(),
+            LexError::UnterminatedChar { .. } => "unterminated character literal".to_string(),
+            LexError::UnterminatedComment { .. } => "unterminated block comment".to_string(),
+            LexError::EmptyChar { .. } => "empty character literal".to_string(),
+            LexError::CharTooLong { .. } => {
+                "character literal may only contain one character".to_string()
+            }
+            LexError::InvalidDigit { radix, .. } => {
+                format!("invalid digit for a base {} literal", radix)
+            }
+            LexError::MissingDigits { .. } => "missing digits after the base prefix".to_string(),
+            LexError::InvalidSuffix { .. } => "invalid suffix for number literal".to_string(),
+            LexError::IntegerOverflow { ty, .. } => {
+                format!("integer literal is too large for `{}`", ty.as_str())
+            }
+            LexError::InvalidEscape { .. } => "invalid escape sequence".to_string(),
+        }
+    }
+
+    pub fn labels(&self) -> Vec<Label> {
+        let primary = |message: String| Label {
+            span: self.span,
+            message,
+            primary: true,
+        };
+
+        match self.error {
+            LexError::UnknownCharacter { .. } => vec![primary("not recognized".to_string())],
+            LexError::UnexpectedClose {
+                bracket,
+                open: Some((open, offset)),
+                ..
+            } => vec![
+                Label {
+                    span: Span::in_file(self.span.file, offset, offset + 1),
+                    message: format!("`{}` opened here", open),
+                    primary: false,
+                },
+                primary(format!(
+                    "expected `{}`, found `{}`",
+                    matching_close(open),
+                    bracket
+                )),
+            ],
+            LexError::UnexpectedClose { .. } => {
+                vec![primary("no open bracket to close".to_string())]
+            }
+            LexError::UnclosedOpen { bracket, .. } => {
+                vec![primary(format!("`{}` is never closed", bracket))]
+            }
+            LexError::InvalidUtf8 { len, .. } => vec![primary(format!(
+                "{} byte{} that are not valid UTF-8",
+                len,
+                if len == 1 { "" } else { "s" }
+            ))],
+            LexError::UnterminatedString { .. } => {
+                vec![primary("string starts here".to_string())]
+            }
+            LexError::UnterminatedRawString { hashes, .. } => vec![primary(format!(
+                "raw string starts here and is never closed by `\"{}`",
+                "#".repeat(hashes)
+            ))],
+            LexError::UnterminatedChar { .. } => vec![primary("expected `'`".to_string())],
+            LexError::UnterminatedComment { .. } => {
+                vec![primary("comment starts here".to_string())]
+            }
+            LexError::EmptyChar { .. } => vec![primary("expected a character".to_string())],
+            LexError::CharTooLong { .. } => vec![primary(
+                "use double quotes for a string, or drop the closing quote for a label".to_string(),
+            )],
+            LexError::InvalidDigit { .. } => vec![primary("invalid digit".to_string())],
+            LexError::MissingDigits { .. } => vec![primary("expected digits".to_string())],
+            LexError::InvalidSuffix { .. } => vec![primary(
+                "expected an integer type such as `u8`, `i32` or `usize`".to_string(),
+            )],
+            LexError::IntegerOverflow { ty, .. } => {
+                vec![primary(format!("the maximum value is {}", ty.max_value()))]
+            }
+            LexError::InvalidEscape { reason, .. } => vec![primary(reason.to_string())],
+        }
+    }
+}
+
+fn matching_close(open: char) -> char {
+    match open {
+        '(' => ')',
+        '{' => '}',
+        '[' => ']',
+        other => other,
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Renders diagnostics as source snippets with the offending code underlined, either as plain
+/// text or with ANSI colors.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Renderer {
+    color: bool,
+}
+
+impl Renderer {
+    pub fn plain() -> Renderer {
+        Renderer { color: false }
+    }
+
+    pub fn colored() -> Renderer {
+        Renderer { color: true }
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn render(&self, map: &SourceMap, diagnostic: &Diagnostic) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            self.paint(RED, "error"),
+            self.paint(BOLD, &format!(": {}", diagnostic.message()))
+        );
+
+        let Some(location) = map.resolve(diagnostic.span) else {
+            return out;
+        };
+
+        let mut labels = diagnostic.labels();
+        labels.sort_by_key(|label| label.span.start);
+
+        let lines: Vec<_> = labels
+            .iter()
+            .filter_map(|label| map.resolve(label.span).map(|location| (label, location)))
+            .collect();
+        let width = lines
+            .iter()
+            .map(|(_, location)| location.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = self.paint(BLUE, &format!("{} |", " ".repeat(width)));
+
+        let _ = writeln!(
+            out,
+            "{}{} {}:{}:{}",
+            " ".repeat(width),
+            self.paint(BLUE, "-->"),
+            location.name,
+            location.line,
+            location.column
+        );
+        let _ = writeln!(out, "{}", gutter);
+
+        // Labels are sorted, so those on the same line are adjacent and share one copy of it.
+        let mut previous = None;
+        for (label, location) in lines {
+            let text = location.line_text;
+            let start = (location.column - 1).min(text.len());
+            let end = (start + label.span.len()).min(text.len());
+            let indent = text[..start].chars().count();
+            let length = text[start..end].chars().count().max(1);
+
+            let (marker, color) = if label.primary {
+                ("^", RED)
+            } else {
+                ("-", BLUE)
+            };
+
+            if previous != Some((location.file, location.line)) {
+                let _ = writeln!(
+                    out,
+                    "{} {}",
+                    self.paint(BLUE, &format!("{:>width$} |", location.line)),
+                    text
+                );
+            }
+            previous = Some((location.file, location.line));
+
+            let _ = writeln!(
+                out,
+                "{} {}{}",
+                gutter,
+                " ".repeat(indent),
+                self.paint(
+                    color,
+                    &format!("{} {}", marker.repeat(length), label.message)
+                )
+            );
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostics(map: &SourceMap, file: crate::FileId) -> Vec<Diagnostic> {
+        let mut lexer = map.lexer(file).unwrap().with_recovery();
+        lexer.by_ref().for_each(drop);
+        lexer.take_diagnostics()
+    }
+
+    #[test]
+    fn test_render_unexpected_close() {
+        let mut map = SourceMap::new();
+        let file = map.add("main.lx", "let mut five = 5; }");
+
+        let rendered = Renderer::plain().render(&map, &diagnostics(&map, file)[0]);
+
+        assert_eq!(
+            rendered,
+            "error: unexpected close bracket `}`\n\
+             \x20--> main.lx:1:19\n\
+             \x20 |\n\
+             1 | let mut five = 5; }\n\
+             \x20 |                   ^ no open bracket to close\n"
+        );
+    }
+
+    #[test]
+    fn test_render_mismatch_labels_opener() {
+        let mut map = SourceMap::new();
+        let file = map.add("main.lx", "let v = (1,\n    2];");
+
+        let rendered = Renderer::plain().render(&map, &diagnostics(&map, file)[0]);
+
+        assert_eq!(
+            rendered,
+            "error: mismatched close bracket `]`\n\
+             \x20--> main.lx:2:6\n\
+             \x20 |\n\
+             1 | let v = (1,\n\
+             \x20 |         - `(` opened here\n\
+             2 |     2];\n\
+             \x20 |      ^ expected `)`, found `]`\n"
+        );
+    }
+
+    #[test]
+    fn test_render_labels_on_one_line() {
+        let mut map = SourceMap::new();
+        let file = map.add("main.lx", "let v = (1];");
+
+        let rendered = Renderer::plain().render(&map, &diagnostics(&map, file)[0]);
+
+        assert_eq!(
+            rendered,
+            "error: mismatched close bracket `]`\n\
+             \x20--> main.lx:1:11\n\
+             \x20 |\n\
+             1 | let v = (1];\n\
+             \x20 |         - `(` opened here\n\
+             \x20 |           ^ expected `)`, found `]`\n"
+        );
+    }
+
+    #[test]
+    fn test_render_colored() {
+        let mut map = SourceMap::new();
+        let file = map.add("main.lx", "a $ b");
+
+        let rendered = Renderer::colored().render(&map, &diagnostics(&map, file)[0]);
+
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: unknown character '$'"));
+        assert!(rendered.contains("\x1b[1;31m^ not recognized\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_without_source() {
+        let map = SourceMap::new();
+        let mut lexer = crate::Lexer::new(b"(").with_recovery();
+        lexer.by_ref().for_each(drop);
+
+        assert_eq!(
+            Renderer::plain().render(&map, &lexer.diagnostics()[0]),
+            "error: unclosed bracket `(`\n"
+        );
+    }
+}
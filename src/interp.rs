@@ -0,0 +1,808 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::parser::{BinaryOp, Block, Expr, ExprKind, FnDecl, Ident, Item, ParseError, Parser};
+use crate::parser::{Stmt, UnaryOp};
+use crate::{Lexer, Span};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Unit,
+}
+
+impl Value {
+    /// The name of the value's type, as written in type annotations.
+    pub fn type_name(self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Bool(_) => "bool",
+            Value::Unit => "()",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
+
+/// An error raised while running a program, with the span of the code that raised it. Resolve
+/// the span with a `SourceMap` to get its line and column.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    Overflow,
+    UnknownVariable(String),
+    UnknownFunction(String),
+    UnknownType(String),
+    AssignToImmutable(String),
+    InvalidAssignTarget,
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    ArgumentCount {
+        expected: usize,
+        found: usize,
+    },
+    /// Expressions, including calls, nested deeper than `MAX_DEPTH`.
+    StackOverflow,
+    /// Syntax the parser accepts but the interpreter cannot run yet, such as float literals.
+    Unsupported(&'static str),
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.kind {
+            RuntimeErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeErrorKind::Overflow => write!(f, "Integer overflow"),
+            RuntimeErrorKind::UnknownVariable(name) => write!(f, "Unknown variable `{}`", name),
+            RuntimeErrorKind::UnknownFunction(name) => write!(f, "Unknown function `{}`", name),
+            RuntimeErrorKind::UnknownType(name) => write!(f, "Unknown type `{}`", name),
+            RuntimeErrorKind::AssignToImmutable(name) => {
+                write!(f, "Cannot assign twice to immutable variable `{}`", name)
+            }
+            RuntimeErrorKind::InvalidAssignTarget => write!(f, "Invalid assignment target"),
+            RuntimeErrorKind::TypeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Expected a value of type `{}`, found `{}`",
+                    expected, found
+                )
+            }
+            RuntimeErrorKind::ArgumentCount { expected, found } => write!(
+                f,
+                "Expected {} argument{}, found {}",
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found
+            ),
+            RuntimeErrorKind::StackOverflow => write!(f, "Call stack overflow"),
+            RuntimeErrorKind::Unsupported(what) => write!(f, "Unsupported {}", what),
+        }?;
+        write!(f, " at byte {}", self.span.start)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    Parse(ParseError),
+    Runtime(RuntimeError),
+}
+
+impl Error {
+    pub fn offset(&self) -> usize {
+        match self {
+            Error::Parse(error) => error.offset(),
+            Error::Runtime(error) => error.span.start,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Parse(error) => error.fmt(f),
+            Error::Runtime(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(error: RuntimeError) -> Self {
+        Error::Runtime(error)
+    }
+}
+
+/// How deeply expressions, including calls and blocks, may nest before a program is stopped
+/// with `StackOverflow`. Running this deep fits in a 1 MiB stack, the smallest main thread stack
+/// of common platforms, even in debug builds.
+pub const MAX_DEPTH: usize = 128;
+
+struct Binding {
+    value: Value,
+    mutable: bool,
+}
+
+/// Runs programs by walking their syntax tree.
+///
+/// Variables are local to the call they are bound in. Functions are visible throughout the
+/// block that declares them, including before their declaration and inside their own body, and
+/// in every block nested within it.
+#[derive(Default)]
+pub struct Interpreter {
+    /// The scopes of the current call, innermost last.
+    scopes: Vec<HashMap<String, Binding>>,
+    /// Scopes of the calls below the current one.
+    callers: Vec<Vec<HashMap<String, Binding>>>,
+    /// The functions declared by each enclosing block, innermost last.
+    functions: Vec<HashMap<String, Rc<FnDecl>>>,
+    /// How many expressions are being evaluated inside each other.
+    depth: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    /// Runs `program` and returns the value of its trailing expression.
+    pub fn run(&mut self, program: &Block) -> Result<Value, RuntimeError> {
+        self.block(program)
+    }
+
+    fn block(&mut self, block: &Block) -> Result<Value, RuntimeError> {
+        let functions = block
+            .stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Item(Item::Fn(decl)) => Some((decl.name.name.clone(), Rc::new(decl.clone()))),
+                _ => None,
+            })
+            .collect();
+
+        self.scopes.push(HashMap::new());
+        self.functions.push(functions);
+        let value = self.block_body(block);
+        self.functions.pop();
+        self.scopes.pop();
+
+        value
+    }
+
+    fn block_body(&mut self, block: &Block) -> Result<Value, RuntimeError> {
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Let(binding) => {
+                    let value = self.expr(&binding.value)?;
+                    if let Some(ty) = &binding.ty {
+                        check_type(ty, value, binding.value.span)?;
+                    }
+                    let scope = self.scopes.last_mut().expect("a block always has a scope");
+                    scope.insert(
+                        binding.name.name.clone(),
+                        Binding {
+                            value,
+                            mutable: binding.mutable,
+                        },
+                    );
+                }
+                Stmt::Expr(expr) => {
+                    self.expr(expr)?;
+                }
+                Stmt::Item(_) => {}
+            }
+        }
+
+        match &block.expr {
+            Some(expr) => self.expr(expr),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    /// Evaluates `expr`, failing instead of overflowing the host stack when expressions nest too
+    /// deeply. Every recursion of the interpreter passes through here.
+    fn expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::StackOverflow,
+                span: expr.span,
+            });
+        }
+
+        self.depth += 1;
+        let value = self.eval(expr);
+        self.depth -= 1;
+        value
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        let error = |kind| RuntimeError {
+            kind,
+            span: expr.span,
+        };
+
+        match &expr.kind {
+            ExprKind::Integer(value) => i64::try_from(*value)
+                .map(Value::Int)
+                .map_err(|_| error(RuntimeErrorKind::Overflow)),
+            ExprKind::Float(_) => Err(error(RuntimeErrorKind::Unsupported("float literal"))),
+            ExprKind::Bool(value) => Ok(Value::Bool(*value)),
+            ExprKind::Ident(name) => match self.lookup(name) {
+                Some(binding) => Ok(binding.value),
+                None => Err(error(RuntimeErrorKind::UnknownVariable(name.clone()))),
+            },
+            ExprKind::Unary { op, operand } => self.unary(*op, operand, expr.span),
+            ExprKind::Binary { .. } => self.binary_chain(expr),
+            ExprKind::Assign { op, target, value } => self.assign(*op, target, value, expr.span),
+            ExprKind::Call { callee, args } => self.call(callee, args, expr.span),
+            ExprKind::Field { .. } => Err(error(RuntimeErrorKind::Unsupported("field access"))),
+            ExprKind::Index { .. } => Err(error(RuntimeErrorKind::Unsupported("indexing"))),
+            ExprKind::Block(block) => self.block(block),
+        }
+    }
+
+    /// Evaluates a chain of binary operators such as `1 + 2 + 3`, which parses as nested left
+    /// operands, in a loop so that the length of the chain does not count as nesting.
+    fn binary_chain(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        let mut operators = Vec::new();
+        let mut first = expr;
+        while let ExprKind::Binary { op, lhs, rhs } = &first.kind {
+            operators.push((*op, rhs.as_ref(), first.span));
+            first = lhs;
+        }
+
+        let mut value = self.expr(first)?;
+        let mut lhs_span = first.span;
+        for (op, rhs, span) in operators.into_iter().rev() {
+            value = match (op, value) {
+                (BinaryOp::And, Value::Bool(false)) => Value::Bool(false),
+                (BinaryOp::Or, Value::Bool(true)) => Value::Bool(true),
+                (BinaryOp::And | BinaryOp::Or, Value::Bool(_)) => Value::Bool(self.bool(rhs)?),
+                (BinaryOp::And | BinaryOp::Or, value) => {
+                    return Err(mismatch("bool", value, lhs_span))
+                }
+                (op, lhs) => {
+                    let rhs_value = self.expr(rhs)?;
+                    binary(op, lhs, rhs_value, span, rhs.span)?
+                }
+            };
+            lhs_span = span;
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self, op: UnaryOp, operand: &Expr, span: Span) -> Result<Value, RuntimeError> {
+        // A negated literal is folded so that `-9223372036854775808` never needs its magnitude
+        // as an `i64`.
+        if let (UnaryOp::Neg, ExprKind::Integer(value)) = (op, &operand.kind) {
+            return i128::try_from(*value)
+                .ok()
+                .and_then(|value| i64::try_from(-value).ok())
+                .map(Value::Int)
+                .ok_or(RuntimeError {
+                    kind: RuntimeErrorKind::Overflow,
+                    span,
+                });
+        }
+
+        match (op, self.expr(operand)?) {
+            (UnaryOp::Neg, Value::Int(value)) => {
+                value.checked_neg().map(Value::Int).ok_or(RuntimeError {
+                    kind: RuntimeErrorKind::Overflow,
+                    span,
+                })
+            }
+            (UnaryOp::Not, Value::Int(value)) => Ok(Value::Int(!value)),
+            (UnaryOp::Not, Value::Bool(value)) => Ok(Value::Bool(!value)),
+            (_, value) => Err(mismatch("int", value, operand.span)),
+        }
+    }
+
+    fn assign(
+        &mut self,
+        op: Option<BinaryOp>,
+        target: &Expr,
+        value: &Expr,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        let ExprKind::Ident(name) = &target.kind else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidAssignTarget,
+                span: target.span,
+            });
+        };
+
+        let mut new = self.expr(value)?;
+        let binding = self.lookup(name).ok_or(RuntimeError {
+            kind: RuntimeErrorKind::UnknownVariable(name.clone()),
+            span: target.span,
+        })?;
+        if !binding.mutable {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::AssignToImmutable(name.clone()),
+                span,
+            });
+        }
+        let old = binding.value;
+
+        if let Some(op) = op {
+            new = binary(op, old, new, span, value.span)?;
+        }
+        if new.type_name() != old.type_name() {
+            return Err(mismatch(old.type_name(), new, value.span));
+        }
+
+        self.lookup_mut(name)
+            .expect("the binding was just found")
+            .value = new;
+        Ok(Value::Unit)
+    }
+
+    fn bool(&mut self, expr: &Expr) -> Result<bool, RuntimeError> {
+        match self.expr(expr)? {
+            Value::Bool(value) => Ok(value),
+            value => Err(mismatch("bool", value, expr.span)),
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> Result<Value, RuntimeError> {
+        let ExprKind::Ident(name) = &callee.kind else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::Unsupported("call of a non-function"),
+                span: callee.span,
+            });
+        };
+
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.expr(arg)?);
+        }
+
+        let Some(depth) = self
+            .functions
+            .iter()
+            .rposition(|functions| functions.contains_key(name))
+        else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::UnknownFunction(name.to_string()),
+                span: callee.span,
+            });
+        };
+        let decl = Rc::clone(&self.functions[depth][name.as_str()]);
+
+        if values.len() != decl.params.len() {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::ArgumentCount {
+                    expected: decl.params.len(),
+                    found: values.len(),
+                },
+                span,
+            });
+        }
+
+        let mut params = HashMap::new();
+        for ((param, value), arg) in decl.params.iter().zip(values).zip(args) {
+            check_type(&param.ty, value, arg.span)?;
+            params.insert(
+                param.name.name.clone(),
+                Binding {
+                    value,
+                    mutable: false,
+                },
+            );
+        }
+
+        // The body sees the functions visible where it was declared, not those of the caller.
+        let hidden = self.functions.split_off(depth + 1);
+        let caller = std::mem::replace(&mut self.scopes, vec![params]);
+        self.callers.push(caller);
+
+        let value = self.block(&decl.body);
+
+        self.scopes = self.callers.pop().expect("pushed above");
+        self.functions.extend(hidden);
+
+        let value = value?;
+        let body_span = decl
+            .body
+            .expr
+            .as_ref()
+            .map_or(decl.body.span, |expr| expr.span);
+        match &decl.ret {
+            Some(ret) => check_type(ret, value, body_span)?,
+            None if value != Value::Unit => return Err(mismatch("()", value, body_span)),
+            None => {}
+        }
+        Ok(value)
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+}
+
+fn mismatch(expected: &'static str, found: Value, span: Span) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::TypeMismatch {
+            expected,
+            found: found.type_name(),
+        },
+        span,
+    }
+}
+
+/// Checks `value` against the type annotation `ty`.
+fn check_type(ty: &Ident, value: Value, span: Span) -> Result<(), RuntimeError> {
+    let expected = match ty.name.as_str() {
+        "int" => "int",
+        "bool" => "bool",
+        _ => {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::UnknownType(ty.name.clone()),
+                span: ty.span,
+            })
+        }
+    };
+
+    if value.type_name() != expected {
+        return Err(mismatch(expected, value, span));
+    }
+    Ok(())
+}
+
+/// Applies a binary operator other than `&&` and `||`. `span` covers the whole operation and
+/// `rhs_span` the right operand, which division and shifts blame for a bad divisor or amount.
+fn binary(
+    op: BinaryOp,
+    lhs: Value,
+    rhs: Value,
+    span: Span,
+    rhs_span: Span,
+) -> Result<Value, RuntimeError> {
+    let error = |kind| RuntimeError { kind, span };
+
+    let (lhs, rhs) = match (op, lhs, rhs) {
+        (BinaryOp::Eq, lhs, rhs) if lhs.type_name() == rhs.type_name() => {
+            return Ok(Value::Bool(lhs == rhs))
+        }
+        (BinaryOp::Ne, lhs, rhs) if lhs.type_name() == rhs.type_name() => {
+            return Ok(Value::Bool(lhs != rhs))
+        }
+        (_, Value::Int(lhs), Value::Int(rhs)) => (lhs, rhs),
+        (_, Value::Int(_), rhs) => return Err(mismatch("int", rhs, rhs_span)),
+        (_, lhs, _) => return Err(mismatch("int", lhs, span)),
+    };
+
+    let checked = |value: Option<i64>| {
+        value
+            .map(Value::Int)
+            .ok_or(error(RuntimeErrorKind::Overflow))
+    };
+    let shift = u32::try_from(rhs).ok();
+
+    match op {
+        BinaryOp::Add => checked(lhs.checked_add(rhs)),
+        BinaryOp::Sub => checked(lhs.checked_sub(rhs)),
+        BinaryOp::Mul => checked(lhs.checked_mul(rhs)),
+        BinaryOp::Div | BinaryOp::Rem if rhs == 0 => Err(RuntimeError {
+            kind: RuntimeErrorKind::DivisionByZero,
+            span: rhs_span,
+        }),
+        BinaryOp::Div => checked(lhs.checked_div(rhs)),
+        BinaryOp::Rem => checked(lhs.checked_rem(rhs)),
+        BinaryOp::Lt => Ok(Value::Bool(lhs < rhs)),
+        BinaryOp::Gt => Ok(Value::Bool(lhs > rhs)),
+        BinaryOp::Le => Ok(Value::Bool(lhs <= rhs)),
+        BinaryOp::Ge => Ok(Value::Bool(lhs >= rhs)),
+        BinaryOp::Eq => Ok(Value::Bool(lhs == rhs)),
+        BinaryOp::Ne => Ok(Value::Bool(lhs != rhs)),
+        BinaryOp::BitAnd => Ok(Value::Int(lhs & rhs)),
+        BinaryOp::BitOr => Ok(Value::Int(lhs | rhs)),
+        BinaryOp::BitXor => Ok(Value::Int(lhs ^ rhs)),
+        BinaryOp::Shl => checked(shift.and_then(|shift| lhs.checked_shl(shift))),
+        BinaryOp::Shr => checked(shift.and_then(|shift| lhs.checked_shr(shift))),
+        BinaryOp::And | BinaryOp::Or => {
+            unreachable!("short-circuiting operators are evaluated lazily")
+        }
+    }
+}
+
+/// Parses and runs the program read by `lexer`, returning the value of its trailing expression.
+pub fn run(lexer: Lexer<'_>) -> Result<Value, Error> {
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program()?;
+    parser.finish()?;
+    Ok(Interpreter::new().run(&program)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SourceMap;
+
+    fn eval(source: &str) -> Result<Value, Error> {
+        run(Lexer::new(source.as_bytes()))
+    }
+
+    fn runtime_error(source: &str) -> RuntimeError {
+        match eval(source) {
+            Err(Error::Runtime(error)) => error,
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    /// Runs `1` with `!` applied `count` times, built directly so that it may nest deeper than the
+    /// parser allows.
+    fn inverted(count: usize) -> Result<Value, RuntimeError> {
+        let span = Span::new(0, 1);
+        let mut expr = Expr {
+            kind: ExprKind::Integer(1),
+            span,
+        };
+        for _ in 0..count {
+            expr = Expr {
+                kind: ExprKind::Unary {
+                    op: UnaryOp::Not,
+                    operand: Box::new(expr),
+                },
+                span,
+            };
+        }
+
+        let program = Block {
+            stmts: Vec::new(),
+            expr: Some(Box::new(expr)),
+            span,
+        };
+        Interpreter::new().run(&program)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("1 + 2 * 3 - 4"), Ok(Value::Int(3)));
+        assert_eq!(eval("(1 + 2) * -3"), Ok(Value::Int(-9)));
+        assert_eq!(eval("7 / 2 + 7 % 2"), Ok(Value::Int(4)));
+        assert_eq!(eval("1 << 4 | 1"), Ok(Value::Int(17)));
+        assert_eq!(eval("1 < 2 && 2 > 3 || true"), Ok(Value::Bool(true)));
+        assert_eq!(eval("0x10 + 0b1 + 1_000"), Ok(Value::Int(1017)));
+        assert_eq!(eval("-9223372036854775808"), Ok(Value::Int(i64::MIN)));
+        assert_eq!(
+            eval(&format!("{}1", "1 + ".repeat(5000))),
+            Ok(Value::Int(5001))
+        );
+        assert_eq!(
+            eval(&format!("{}true", "false || ".repeat(5000))),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(eval("--9223372036854775807"), Ok(Value::Int(i64::MAX)));
+        assert_eq!(eval(""), Ok(Value::Unit));
+    }
+
+    #[test]
+    fn test_bindings() {
+        assert_eq!(eval("let x = 2; let y: int = x * 3; y"), Ok(Value::Int(6)));
+        assert_eq!(
+            eval("let mut total = 1; total = total + 1; total *= 10; total"),
+            Ok(Value::Int(20))
+        );
+        assert_eq!(
+            eval("let x = 1; let y = { let x = 2; x * 10 }; x + y"),
+            Ok(Value::Int(21))
+        );
+        assert_eq!(eval("let x = 1; let x = x + 1; x"), Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(
+            eval("fn add(x: int, y: int) -> int { x + y } add(2, add(3, 4))"),
+            Ok(Value::Int(9))
+        );
+        assert_eq!(
+            eval("let y = square(5); fn square(x: int) -> int { x * x } y"),
+            Ok(Value::Int(25))
+        );
+        assert_eq!(
+            eval(
+                "fn area(w: int, h: int) -> int {
+                     fn double(x: int) -> int { x * 2 }
+                     let mut total = w * h;
+                     total = double(total);
+                     total
+                 }
+                 fn is_big(n: int) -> bool { n > 100 }
+                 is_big(area(10, 6))"
+            ),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(eval("fn noop() {} noop()"), Ok(Value::Unit));
+        assert_eq!(eval("{ 1 }"), Ok(Value::Int(1)));
+        assert_eq!(eval("fn f() -> int { { 1 } } f()"), Ok(Value::Int(1)));
+        assert_eq!(
+            eval("fn f() -> int { let x = 1; { x + 1 } } f()"),
+            Ok(Value::Int(2))
+        );
+    }
+
+    #[test]
+    fn test_function_scoping() {
+        let error = runtime_error("let x = 1; fn f() -> int { x } f()");
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::UnknownVariable("x".to_string())
+        );
+
+        let error =
+            runtime_error("fn f() -> int { g() } fn h() -> int { fn g() -> int { 1 } f() } h()");
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::UnknownFunction("g".to_string())
+        );
+
+        assert_eq!(
+            eval("fn f() -> int { fn g() -> int { 2 } g() } fn g() -> int { 1 } f() + g()"),
+            Ok(Value::Int(3))
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let error = runtime_error("let a = 1;\nlet b = 0;\na / b");
+        assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+        assert_eq!(error.span, Span::new(26, 27));
+        assert_eq!(error.to_string(), "Division by zero at byte 26");
+
+        let error = runtime_error("1 + y");
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::UnknownVariable("y".to_string())
+        );
+        assert_eq!(error.span, Span::new(4, 5));
+
+        let error = runtime_error("true && false || 1 && true");
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::TypeMismatch {
+                expected: "bool",
+                found: "int"
+            }
+        );
+        assert_eq!(error.span, Span::new(17, 18));
+        assert_eq!(runtime_error("1 - 2 && true").span, Span::new(0, 5));
+        assert_eq!(runtime_error("1 + 2 + 3 / 0 + 4").span, Span::new(12, 13));
+        assert_eq!(eval("false && y || true"), Ok(Value::Bool(true)));
+
+        let error = runtime_error("let x = 1; x = 2;");
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::AssignToImmutable("x".to_string())
+        );
+        assert_eq!(error.span, Span::new(11, 16));
+
+        assert_eq!(
+            runtime_error("9223372036854775807 + 1").kind,
+            RuntimeErrorKind::Overflow
+        );
+        assert_eq!(
+            runtime_error("--9223372036854775808").kind,
+            RuntimeErrorKind::Overflow
+        );
+        assert_eq!(
+            runtime_error("fn f(x: int) -> int { x } f(true)").kind,
+            RuntimeErrorKind::TypeMismatch {
+                expected: "int",
+                found: "bool"
+            }
+        );
+        assert_eq!(
+            runtime_error("fn f(x: int) -> int { x } f(1, 2)").kind,
+            RuntimeErrorKind::ArgumentCount {
+                expected: 1,
+                found: 2
+            }
+        );
+        assert_eq!(
+            runtime_error("fn f() -> bool { 1 } f()").kind,
+            RuntimeErrorKind::TypeMismatch {
+                expected: "bool",
+                found: "int"
+            }
+        );
+        assert_eq!(
+            runtime_error("let x: float = 1;").kind,
+            RuntimeErrorKind::UnknownType("float".to_string())
+        );
+        assert_eq!(
+            runtime_error("fn f(n: int) -> int { f(n + 1) } f(0)").kind,
+            RuntimeErrorKind::StackOverflow
+        );
+        assert_eq!(
+            inverted(MAX_DEPTH).unwrap_err().kind,
+            RuntimeErrorKind::StackOverflow
+        );
+        assert!(inverted(MAX_DEPTH - 1).is_ok());
+        assert_eq!(
+            runtime_error("1 = 2;").kind,
+            RuntimeErrorKind::InvalidAssignTarget
+        );
+        assert!(matches!(eval("let x = ;"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let mut map = SourceMap::new();
+        let file = map.add(
+            "main.lx",
+            "fn div(a: int, b: int) -> int {\n    a / b\n}\ndiv(1, 0)\n",
+        );
+
+        let error = run(map.lexer(file).unwrap()).unwrap_err();
+        let Error::Runtime(error) = error else {
+            panic!("expected a runtime error");
+        };
+        let location = map.resolve(error.span).unwrap();
+
+        assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+        assert_eq!(
+            (location.name, location.line, location.column),
+            ("main.lx", 2, 9)
+        );
+        assert_eq!(location.line_text, "    a / b");
+    }
+
+    #[test]
+    fn test_deep_recursion_on_a_small_stack() {
+        let sources = [
+            "fn f(n: int) -> int { if_(n) }
+             fn if_(n: int) -> int { let a = { { { { { g(n) } } } } }; a }
+             fn g(n: int) -> int { f(n + 1) }
+             f(0)",
+            "fn f(n: int) -> int { f(n) } f(0)",
+            "fn f(n: int) -> int { let x = f(n); x } f(0)",
+            "fn f(n: int) -> int { 1 + f(f(n)) } f(0)",
+        ];
+
+        for source in sources {
+            let error = std::thread::Builder::new()
+                .stack_size(1024 * 1024)
+                .spawn(move || runtime_error(source))
+                .unwrap()
+                .join()
+                .unwrap();
+
+            assert_eq!(error.kind, RuntimeErrorKind::StackOverflow);
+        }
+    }
+}
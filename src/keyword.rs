@@ -0,0 +1,121 @@
+use crate::TokenKind::{self, *};
+
+const KEYWORDS: [(&[u8], TokenKind); 22] = [
+    (b"let", Let),
+    (b"mut", Mut),
+    (b"fn", Fn),
+    (b"if", If),
+    (b"else", Else),
+    (b"while", While),
+    (b"for", For),
+    (b"in", In),
+    (b"loop", Loop),
+    (b"break", Break),
+    (b"continue", Continue),
+    (b"return", Return),
+    (b"true", True),
+    (b"false", False),
+    (b"struct", Struct),
+    (b"enum", Enum),
+    (b"match", Match),
+    (b"impl", Impl),
+    (b"pub", Pub),
+    (b"use", Use),
+    (b"const", Const),
+    (b"type", Type),
+];
+
+const TABLE_SIZE: usize = 64;
+
+/// A perfect hash over the keywords: every keyword lands in its own slot of `TABLE`, so a lookup
+/// is one hash and at most one comparison. Must only be called with at least two bytes.
+const fn hash(ident: &[u8]) -> usize {
+    (ident[0] as usize + 2 * ident[1] as usize + 6 * ident.len()) % TABLE_SIZE
+}
+
+const TABLE: [Option<(&[u8], TokenKind)>; TABLE_SIZE] = {
+    let mut table = [None; TABLE_SIZE];
+
+    let mut index = 0;
+    while index < KEYWORDS.len() {
+        let slot = hash(KEYWORDS[index].0);
+        assert!(table[slot].is_none(), "keyword hash collision");
+        table[slot] = Some(KEYWORDS[index]);
+        index += 1;
+    }
+
+    table
+};
+
+/// Words that are only keywords in specific positions and remain valid identifiers elsewhere.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Kw {
+    Union,
+    Async,
+    Default,
+}
+
+impl Kw {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kw::Union => "union",
+            Kw::Async => "async",
+            Kw::Default => "default",
+        }
+    }
+}
+
+pub(crate) fn lookup_contextual(ident: &[u8]) -> Option<Kw> {
+    match ident {
+        b"union" => Some(Kw::Union),
+        b"async" => Some(Kw::Async),
+        b"default" => Some(Kw::Default),
+        _ => None,
+    }
+}
+
+/// The keyword token for `ident`, if it is a keyword.
+pub(crate) fn lookup(ident: &[u8]) -> Option<TokenKind> {
+    if ident.len() < 2 {
+        return None;
+    }
+
+    match TABLE[hash(ident)] {
+        Some((keyword, kind)) if keyword == ident => Some(kind),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_every_keyword() {
+        for (keyword, kind) in KEYWORDS {
+            assert_eq!(lookup(keyword), Some(kind));
+        }
+    }
+
+    #[test]
+    fn test_lookup_non_keywords() {
+        for ident in [
+            "", "x", "le", "lets", "Let", "fns", "iff", "types", "constant", "nu",
+        ] {
+            assert_eq!(lookup(ident.as_bytes()), None, "{}", ident);
+        }
+    }
+
+    #[test]
+    fn test_lookup_contextual() {
+        for (keyword, kind) in KEYWORDS {
+            assert!(kind.is_keyword(), "{:?}", keyword);
+        }
+
+        for kw in [Kw::Union, Kw::Async, Kw::Default] {
+            assert_eq!(lookup_contextual(kw.as_str().as_bytes()), Some(kw));
+            assert_eq!(lookup(kw.as_str().as_bytes()), None);
+        }
+        assert_eq!(lookup_contextual(b"unions"), None);
+    }
+}
@@ -1,25 +1,220 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum BracketState {
+pub mod diagnostic;
+pub mod interp;
+mod keyword;
+mod literal;
+pub mod parser;
+pub mod source_map;
+mod unicode;
+
+use std::fmt::Display;
+
+pub use diagnostic::{Diagnostic, Label, Renderer};
+pub use keyword::Kw;
+pub use literal::{EscapeError, IntSuffix, Radix};
+pub use source_map::{FileId, Location, SourceMap};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum BracketState {
     Open,
     Close,
 }
 
-enum BracketError {
-    UnexpectedClose(char),
-    UnexpectedOpen(char),
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LexError {
+    UnknownCharacter {
+        character: char,
+        offset: usize,
+    },
+    /// `open` is the bracket popped from the stack that the close bracket failed to match.
+    UnexpectedClose {
+        bracket: char,
+        offset: usize,
+        open: Option<(char, usize)>,
+    },
+    UnclosedOpen {
+        bracket: char,
+        offset: usize,
+    },
+    /// Bytes that are not valid UTF-8.
+    InvalidUtf8 {
+        offset: usize,
+        len: usize,
+    },
+    UnterminatedString {
+        offset: usize,
+    },
+    /// A raw string missing its closing quote followed by `hashes` `#` characters.
+    UnterminatedRawString {
+        offset: usize,
+        hashes: usize,
+    },
+    UnterminatedChar {
+        offset: usize,
+    },
+    UnterminatedComment {
+        offset: usize,
+    },
+    EmptyChar {
+        offset: usize,
+    },
+    /// A quoted identifier with more than one character, such as `'ab'`.
+    CharTooLong {
+        offset: usize,
+    },
+    /// A digit that is not valid in the base of a `0b` or `0o` literal.
+    InvalidDigit {
+        offset: usize,
+        radix: u32,
+    },
+    /// A base prefix such as `0x` that is not followed by any digits.
+    MissingDigits {
+        offset: usize,
+    },
+    InvalidSuffix {
+        offset: usize,
+        len: usize,
+    },
+    /// An integer literal whose value does not fit in its suffix type, or in `i64` when it has
+    /// no suffix.
+    IntegerOverflow {
+        offset: usize,
+        ty: IntSuffix,
+    },
+    /// `offset` and `len` cover the escape sequence, starting at its backslash.
+    InvalidEscape {
+        offset: usize,
+        len: usize,
+        reason: EscapeError,
+    },
+}
+
+impl LexError {
+    pub fn offset(&self) -> usize {
+        match *self {
+            LexError::UnknownCharacter { offset, .. }
+            | LexError::UnexpectedClose { offset, .. }
+            | LexError::UnclosedOpen { offset, .. }
+            | LexError::InvalidUtf8 { offset, .. }
+            | LexError::UnterminatedString { offset }
+            | LexError::UnterminatedRawString { offset, .. }
+            | LexError::UnterminatedChar { offset }
+            | LexError::UnterminatedComment { offset }
+            | LexError::EmptyChar { offset }
+            | LexError::CharTooLong { offset }
+            | LexError::InvalidDigit { offset, .. }
+            | LexError::MissingDigits { offset }
+            | LexError::InvalidSuffix { offset, .. }
+            | LexError::IntegerOverflow { offset, .. }
+            | LexError::InvalidEscape { offset, .. } => offset,
+        }
+    }
 }
 
-impl Display for BracketError {
+impl Display for LexError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            BracketError::UnexpectedClose(c) => write!(f, "Unexpected close bracket: {}", c),
-            BracketError::UnexpectedOpen(c) => write!(f, "Unexpected open bracket: {}", c),
+            LexError::UnknownCharacter { character, offset } => {
+                write!(f, "Unknown character: {:?} at byte {}", character, offset)
+            }
+            LexError::UnexpectedClose {
+                bracket, offset, ..
+            } => {
+                write!(
+                    f,
+                    "Unexpected close bracket: {} at byte {}",
+                    bracket, offset
+                )
+            }
+            LexError::UnclosedOpen { bracket, offset } => {
+                write!(f, "Unclosed open bracket: {} at byte {}", bracket, offset)
+            }
+            LexError::InvalidUtf8 { offset, len } => {
+                write!(f, "Invalid UTF-8 in {} bytes at byte {}", len, offset)
+            }
+            LexError::UnterminatedString { offset } => {
+                write!(f, "Unterminated string starting at byte {}", offset)
+            }
+            LexError::UnterminatedRawString { offset, .. } => {
+                write!(f, "Unterminated raw string starting at byte {}", offset)
+            }
+            LexError::UnterminatedChar { offset } => {
+                write!(f, "Unterminated character literal at byte {}", offset)
+            }
+            LexError::UnterminatedComment { offset } => {
+                write!(f, "Unterminated block comment starting at byte {}", offset)
+            }
+            LexError::EmptyChar { offset } => {
+                write!(f, "Empty character literal at byte {}", offset)
+            }
+            LexError::CharTooLong { offset } => write!(
+                f,
+                "Character literal with more than one character at byte {}",
+                offset
+            ),
+            LexError::InvalidDigit { offset, radix } => {
+                write!(
+                    f,
+                    "Invalid digit for a base {} literal at byte {}",
+                    radix, offset
+                )
+            }
+            LexError::MissingDigits { offset } => {
+                write!(f, "Missing digits after the base prefix at byte {}", offset)
+            }
+            LexError::InvalidSuffix { offset, .. } => {
+                write!(f, "Invalid number suffix at byte {}", offset)
+            }
+            LexError::IntegerOverflow { offset, ty } => write!(
+                f,
+                "Integer literal at byte {} does not fit in {}",
+                offset,
+                ty.as_str()
+            ),
+            LexError::InvalidEscape { offset, reason, .. } => {
+                write!(f, "Invalid escape sequence at byte {}: {}", offset, reason)
+            }
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum TokenType {
+impl std::error::Error for LexError {}
+
+/// A half-open range of byte offsets into the source of `file`.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Creates a span that does not belong to any file of a `SourceMap`.
+    pub fn new(start: usize, end: usize) -> Span {
+        Span::in_file(FileId::ANONYMOUS, start, end)
+    }
+
+    pub fn in_file(file: FileId, start: usize, end: usize) -> Span {
+        Span { file, start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::in_file(self.file, self.start, other.end)
+    }
+}
+
+/// The kind of a `Token`. New kinds may be added as the language grows.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[non_exhaustive]
+pub enum TokenKind {
     Paren(BracketState),
     Curly(BracketState),
     Square(BracketState),
@@ -37,277 +232,2593 @@ enum TokenType {
     Star,
     Ident,
     Integer,
+    Float,
+    String,
+    StringStart,
+    StringMiddle,
+    StringEnd,
+    RawString,
+    Char,
+    Label,
+    DocComment,
+    Whitespace,
+    Newline,
+    Comment,
     Bigger,
     Smaller,
+    Equal,
+    NotEqual,
+    BiggerEqual,
+    SmallerEqual,
+    AndAnd,
+    OrOr,
+    PlusAssign,
+    MinusAssign,
+    StarAssign,
+    SlashAssign,
+    FatArrow,
+    DoubleColon,
+    DotDot,
+    DotDotEqual,
+    ShiftLeft,
+    ShiftRight,
+    Bang,
+    Ampersand,
+    Pipe,
+    Percent,
+    Caret,
+    Question,
+    At,
     Mut,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Loop,
+    Break,
+    Continue,
+    Return,
+    True,
+    False,
+    Struct,
+    Enum,
+    Match,
+    Impl,
+    Pub,
+    Use,
+    Const,
+    Type,
     Eof,
+    Error,
+}
+
+impl TokenKind {
+    /// A human-readable name for diagnostics, such as "`->`" or "identifier".
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Paren(BracketState::Open) => "`(`",
+            Paren(BracketState::Close) => "`)`",
+            Curly(BracketState::Open) => "`{`",
+            Curly(BracketState::Close) => "`}`",
+            Square(BracketState::Open) => "`[`",
+            Square(BracketState::Close) => "`]`",
+            Let => "`let`",
+            Fn => "`fn`",
+            Colon => "`:`",
+            Arrow => "`->`",
+            Assign => "`=`",
+            Comma => "`,`",
+            Dot => "`.`",
+            Minus => "`-`",
+            Plus => "`+`",
+            Semicolon => "`;`",
+            Slash => "`/`",
+            Star => "`*`",
+            Ident => "identifier",
+            Integer => "integer literal",
+            Float => "float literal",
+            String => "string literal",
+            StringStart => "start of an interpolated string",
+            StringMiddle => "interpolated string segment",
+            StringEnd => "end of an interpolated string",
+            RawString => "raw string literal",
+            Char => "character literal",
+            Label => "label",
+            DocComment => "doc comment",
+            Whitespace => "whitespace",
+            Newline => "newline",
+            Comment => "comment",
+            Bigger => "`>`",
+            Smaller => "`<`",
+            Equal => "`==`",
+            NotEqual => "`!=`",
+            BiggerEqual => "`>=`",
+            SmallerEqual => "`<=`",
+            AndAnd => "`&&`",
+            OrOr => "`||`",
+            PlusAssign => "`+=`",
+            MinusAssign => "`-=`",
+            StarAssign => "`*=`",
+            SlashAssign => "`/=`",
+            FatArrow => "`=>`",
+            DoubleColon => "`::`",
+            DotDot => "`..`",
+            DotDotEqual => "`..=`",
+            ShiftLeft => "`<<`",
+            ShiftRight => "`>>`",
+            Bang => "`!`",
+            Ampersand => "`&`",
+            Pipe => "`|`",
+            Percent => "`%`",
+            Caret => "`^`",
+            Question => "`?`",
+            At => "`@`",
+            Mut => "`mut`",
+            If => "`if`",
+            Else => "`else`",
+            While => "`while`",
+            For => "`for`",
+            In => "`in`",
+            Loop => "`loop`",
+            Break => "`break`",
+            Continue => "`continue`",
+            Return => "`return`",
+            True => "`true`",
+            False => "`false`",
+            Struct => "`struct`",
+            Enum => "`enum`",
+            Match => "`match`",
+            Impl => "`impl`",
+            Pub => "`pub`",
+            Use => "`use`",
+            Const => "`const`",
+            Type => "`type`",
+            Eof => "end of file",
+            Error => "invalid token",
+        }
+    }
+
+    pub fn is_keyword(self) -> bool {
+        matches!(
+            self,
+            Let | Fn
+                | Mut
+                | If
+                | Else
+                | While
+                | For
+                | In
+                | Loop
+                | Break
+                | Continue
+                | Return
+                | True
+                | False
+                | Struct
+                | Enum
+                | Match
+                | Impl
+                | Pub
+                | Use
+                | Const
+                | Type
+        )
+    }
+
+    /// Whether this is one of the operator and punctuation tokens, such as `+`, `->` or `;`.
+    /// Brackets are not operators.
+    pub fn is_operator(self) -> bool {
+        OPERATORS.iter().any(|&(_, kind)| kind == self)
+    }
+
+    /// Whether this is a literal, including the `true` and `false` keywords. The pieces of an
+    /// interpolated string are not literals on their own.
+    pub fn is_literal(self) -> bool {
+        matches!(
+            self,
+            Integer | Float | String | RawString | Char | True | False
+        )
+    }
+
+    pub fn is_open_bracket(self) -> bool {
+        matches!(
+            self,
+            Paren(BracketState::Open) | Curly(BracketState::Open) | Square(BracketState::Open)
+        )
+    }
+
+    pub fn is_close_bracket(self) -> bool {
+        matches!(
+            self,
+            Paren(BracketState::Close) | Curly(BracketState::Close) | Square(BracketState::Close)
+        )
+    }
+
+    /// The bracket that closes this one if it is an open bracket, or opens it if it is a close
+    /// bracket.
+    pub fn matching_bracket(self) -> Option<TokenKind> {
+        let flip = |state| match state {
+            BracketState::Open => BracketState::Close,
+            BracketState::Close => BracketState::Open,
+        };
+
+        match self {
+            Paren(state) => Some(Paren(flip(state))),
+            Curly(state) => Some(Curly(flip(state))),
+            Square(state) => Some(Square(flip(state))),
+            _ => None,
+        }
+    }
+
+    /// How tightly this token binds as a binary operator; higher binds tighter. Assignments bind
+    /// loosest, then `||`, `&&`, comparisons, `|`, `^`, `&`, shifts, `+` and `-`, and finally
+    /// `*`, `/` and `%`.
+    pub fn binary_precedence(self) -> Option<u8> {
+        let precedence = match self {
+            Assign | PlusAssign | MinusAssign | StarAssign | SlashAssign => 1,
+            OrOr => 2,
+            AndAnd => 3,
+            Equal | NotEqual | Smaller | Bigger | SmallerEqual | BiggerEqual => 4,
+            Pipe => 5,
+            Caret => 6,
+            Ampersand => 7,
+            ShiftLeft | ShiftRight => 8,
+            Plus | Minus => 9,
+            Star | Slash | Percent => 10,
+            _ => return None,
+        };
+        Some(precedence)
+    }
+
+    /// Which way a chain of this binary operator groups. Assignments group to the right, every
+    /// other binary operator to the left.
+    pub fn associativity(self) -> Option<Associativity> {
+        match self {
+            Assign | PlusAssign | MinusAssign | StarAssign | SlashAssign => {
+                Some(Associativity::Right)
+            }
+            _ if self.binary_precedence().is_some() => Some(Associativity::Left),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `a op b op c` groups as `(a op b) op c` or `a op (b op c)`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Token<'a> {
-    token_type: TokenType,
+    kind: TokenKind,
     literal: &'a [u8],
+    span: Span,
+    line: usize,
+    column: usize,
+    contextual: Option<Kw>,
 }
 
-impl Token<'_> {
-    fn new(token_type: TokenType, literal: &[u8]) -> Token {
+impl<'a> Token<'a> {
+    fn new(kind: TokenKind, literal: &[u8]) -> Token<'_> {
         Token {
-            token_type,
+            kind,
             literal,
+            span: Span::default(),
+            line: 1,
+            column: 1,
+            contextual: None,
         }
     }
+
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    /// The source bytes of the token.
+    pub fn text(&self) -> &'a [u8] {
+        self.literal
+    }
+
+    /// The source text of the token. Only an `Error` token covering invalid UTF-8 can hold bytes
+    /// that are not text; for those this is the longest valid prefix.
+    pub fn as_str(&self) -> &'a str {
+        match std::str::from_utf8(self.literal) {
+            Ok(text) => text,
+            Err(error) => std::str::from_utf8(&self.literal[..error.valid_up_to()]).unwrap(),
+        }
+    }
+
+    /// The contextual keyword this identifier spells, if any. Contextual keywords are lexed as
+    /// identifiers and only act as keywords where the parser expects them.
+    pub fn contextual(&self) -> Option<Kw> {
+        self.contextual
+    }
+
+    pub fn is_contextual(&self, kw: Kw) -> bool {
+        self.contextual == Some(kw)
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The 1-based line the token starts on.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The 1-based column, in bytes, the token starts at.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+/// How the lexer treats its input. Outside any mode it lexes ordinary tokens.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    /// Lexing the expression inside a `{...}` of the string starting at `string_start`. The `{`
+    /// also leaves a `StringStart` marker on the bracket stack, so brackets opened inside the
+    /// interpolation must be closed before its `}` resumes the string.
+    Interpolation { string_start: usize },
 }
 
 pub struct Lexer<'a> {
     source: &'a [u8],
     position: usize,
-    braces_stack: Vec<TokenType>,
+    braces_stack: Vec<(TokenKind, usize)>,
+    modes: Vec<Mode>,
+    utf8_error: Option<(usize, usize)>,
+    token_start: usize,
+    previous: Option<TokenKind>,
+    file: FileId,
+    recover: bool,
+    trivia: bool,
+    diagnostics: Vec<Diagnostic>,
+    /// Errors found inside a comment or literal that are still to be reported, last first.
+    pending: Vec<LexError>,
+    line: usize,
+    line_start: usize,
+    scanned: usize,
+    /// The offset, line and line start last resolved behind `scanned`.
+    rewound: (usize, usize, usize),
 }
 
-impl Lexer<'_> {
-    pub fn new(source: &[u8]) -> Lexer {
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a [u8]) -> Lexer<'a> {
         Lexer {
             source,
             position: 0,
             braces_stack: Vec::new(),
+            modes: Vec::new(),
+            utf8_error: next_utf8_error(source, 0),
+            token_start: 0,
+            previous: None,
+            file: FileId::ANONYMOUS,
+            recover: false,
+            trivia: false,
+            diagnostics: Vec::new(),
+            pending: Vec::new(),
+            line: 1,
+            line_start: 0,
+            scanned: 0,
+            rewound: (0, 1, 0),
         }
     }
-}
 
-use std::fmt::Display;
+    /// Turns lexing errors into `Error` tokens and collects them as diagnostics instead of
+    /// stopping at the first one, so the whole source is always tokenized.
+    pub fn with_recovery(mut self) -> Self {
+        self.recover = true;
+        self
+    }
+
+    /// Emits whitespace, newlines and comments as `Whitespace`, `Newline` and `Comment` tokens
+    /// instead of skipping them, so concatenating the text of every token reproduces the source.
+    /// Combined with recovery, an unclosed bracket or interpolation is reported by an empty
+    /// `Error` token at the end of the input rather than one repeating its opening text.
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
 
-use TokenType::*;
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
 
-impl<'a> Iterator for Lexer<'a> {
-    type Item = Token<'a>;
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.position >= self.source.len() {
-            if let Some(brace) = self.braces_stack.pop() {
-                let brace_error = match brace {
-                    Paren(_) => BracketError::UnexpectedOpen('('),
-                    Curly(_) => BracketError::UnexpectedOpen('{'),
-                    Square(_) => BracketError::UnexpectedOpen('['),
-                    _ => unreachable!(),
-                };
+    /// Iterates over the tokens as `Result`s instead of panicking on the first lexing error.
+    pub fn try_iter(self) -> TryIter<'a> {
+        TryIter { lexer: self }
+    }
 
-                panic!("{}", brace_error);
-            }
+    /// The byte `offset` bytes past the current position, without consuming anything.
+    pub fn peek_byte(&self, offset: usize) -> Option<u8> {
+        self.source[..self.limit()]
+            .get(self.position + offset)
+            .copied()
+    }
+
+    /// The end of the input that can be lexed before the next invalid UTF-8 sequence. Comments
+    /// and literals are the exception and scan past it.
+    fn limit(&self) -> usize {
+        self.utf8_error
+            .map_or(self.source.len(), |(offset, _)| offset)
+    }
+
+    /// The result the next call to `next_token` will return, without consuming it or touching the
+    /// bracket stack.
+    pub fn peek_token(&self) -> Option<Result<Token<'a>, LexError>> {
+        // A single token can pop at most one bracket, so lexing ahead on a copy of the top of
+        // the stack gives the same result as lexing on the whole stack.
+        let mut lookahead = Lexer {
+            source: self.source,
+            position: self.position,
+            braces_stack: self.braces_stack.last().copied().into_iter().collect(),
+            modes: self.modes.last().copied().into_iter().collect(),
+            utf8_error: self.utf8_error,
+            token_start: self.token_start,
+            previous: self.previous,
+            file: self.file,
+            recover: self.recover,
+            trivia: self.trivia,
+            diagnostics: Vec::new(),
+            pending: self.pending.last().copied().into_iter().collect(),
+            line: self.line,
+            line_start: self.line_start,
+            scanned: self.scanned,
+            rewound: self.rewound,
+        };
+
+        lookahead.next_token()
+    }
+
+    pub fn next_token(&mut self) -> Option<Result<Token<'a>, LexError>> {
+        let result = match self.lex_token() {
+            Err(error) if self.recover => Some(Ok(self.recover_from(error))),
+            result => result.transpose(),
+        };
 
-            return None;
+        if let Some(Ok(token)) = &result {
+            self.previous = Some(token.kind);
         }
 
-        let slice = &self.source[self.position..];
+        result
+    }
 
-        let token = match slice[0] {
-            b' ' | b'\n' | b'\t' => {
-                self.position += 1;
-                return self.next();
+    /// Records `error` as a diagnostic and returns an `Error` token covering the input that
+    /// failed to lex.
+    fn recover_from(&mut self, error: LexError) -> Token<'a> {
+        let offset = error.offset();
+        let (start, end) = match error {
+            LexError::UnclosedOpen { .. } if !self.trivia => (offset, offset + 1),
+            _ => (self.token_start, self.position),
+        };
+        let error_end = match error {
+            LexError::UnclosedOpen { .. } | LexError::UnterminatedString { .. } => offset + 1,
+            LexError::UnterminatedRawString { hashes, .. } => offset + hashes + 2,
+            LexError::InvalidDigit { .. } => offset + 1,
+            LexError::UnterminatedComment { .. } => offset + 2,
+            LexError::InvalidUtf8 { len, .. } => offset + len,
+            LexError::InvalidEscape { len, .. } | LexError::InvalidSuffix { len, .. } => {
+                offset + len
             }
-            b'(' => {
-                let token = Token::new(Paren(BracketState::Open), &slice[..1]);
-                self.braces_stack.push(token.token_type);
-                token
+            _ => self.position,
+        };
+
+        self.diagnostics.push(Diagnostic {
+            error,
+            span: Span::in_file(self.file, offset, error_end),
+        });
+
+        self.locate(Token::new(Error, &self.source[start..end]), start)
+    }
+
+    fn unknown_character(&mut self, slice: &[u8]) -> LexError {
+        let (character, len) = decode_char(slice);
+        let offset = self.position;
+        self.position += len;
+        LexError::UnknownCharacter { character, offset }
+    }
+
+    /// Finds the longest operator at the current position by peeking ahead, so recognizing a
+    /// multi-character operator such as `->` never lexes what follows its first character.
+    fn match_operator(&self) -> Option<(&'static [u8], TokenKind)> {
+        OPERATORS.iter().copied().find(|(operator, _)| {
+            operator
+                .iter()
+                .enumerate()
+                .all(|(offset, &byte)| self.peek_byte(offset) == Some(byte))
+        })
+    }
+
+    /// Lexes an integer or a float. Integers may have a `0x`, `0o` or `0b` base prefix, any
+    /// number may contain `_` separators, and integers may end in a type suffix such as `u8`.
+    ///
+    /// A `.` only starts a fraction when a digit follows it and the number does not directly
+    /// follow a `.` itself, so `1..2` stays a range and `x.0.1` stays a chain of field accesses.
+    fn lex_number(&mut self, slice: &'a [u8]) -> Result<Token<'a>, LexError> {
+        let run = |from: usize, is_digit: fn(&u8) -> bool| {
+            from + slice[from..]
+                .iter()
+                .take_while(|&byte| is_digit(byte) || *byte == b'_')
+                .count()
+        };
+
+        let radix = match slice.get(..2) {
+            Some(b"0x") => 16,
+            Some(b"0o") => 8,
+            Some(b"0b") => 2,
+            _ => 10,
+        };
+
+        let mut end;
+        let mut kind = Integer;
+        let mut invalid = None;
+
+        if radix == 10 {
+            end = run(0, u8::is_ascii_digit);
+
+            if self.previous != Some(Dot)
+                && slice.get(end) == Some(&b'.')
+                && slice.get(end + 1).is_some_and(u8::is_ascii_digit)
+            {
+                end = run(end + 1, u8::is_ascii_digit);
+                kind = Float;
             }
-            b')' => {
-                let token = Token::new(Paren(BracketState::Close), &slice[..1]);
-                if let Some(Paren(BracketState::Open)) = self.braces_stack.pop() {
-                    token
-                } else {
-                    panic!("{}", BracketError::UnexpectedClose(')'));
+
+            if matches!(slice.get(end), Some(b'e' | b'E')) {
+                let sign = matches!(slice.get(end + 1), Some(b'+' | b'-')) as usize;
+                if slice.get(end + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                    end = run(end + 1 + sign, u8::is_ascii_digit);
+                    kind = Float;
                 }
             }
-            b'{' => {
-                let token = Token::new(Curly(BracketState::Open), &slice[..1]);
-                self.braces_stack.push(token.token_type);
-                token
-            }
-            b'}' => {
-                let token = Token::new(Curly(BracketState::Close), &slice[..1]);
-                if let Some(Curly(BracketState::Open)) = self.braces_stack.pop() {
-                    token
-                } else {
-                    panic!("{}", BracketError::UnexpectedClose('}'));
-                }
+        } else {
+            end = match radix {
+                16 => run(2, u8::is_ascii_hexdigit),
+                _ => run(2, u8::is_ascii_digit),
+            };
+
+            let digits = &slice[2..end];
+            if digits.iter().all(|&byte| byte == b'_') {
+                invalid = Some(LexError::MissingDigits {
+                    offset: self.position,
+                });
+            } else if let Some(index) = digits
+                .iter()
+                .position(|&byte| byte != b'_' && radix != 16 && u32::from(byte - b'0') >= radix)
+            {
+                invalid = Some(LexError::InvalidDigit {
+                    offset: self.position + 2 + index,
+                    radix,
+                });
             }
-            b'[' => {
-                let token = Token::new(Square(BracketState::Open), &slice[..1]);
-                self.braces_stack.push(token.token_type);
-                token
+        }
+
+        let suffix = end;
+        while end < slice.len() && (slice[end].is_ascii_alphanumeric() || slice[end] == b'_') {
+            end += 1;
+        }
+
+        if invalid.is_none()
+            && end > suffix
+            && (kind == Float || IntSuffix::from_bytes(&slice[suffix..end]).is_none())
+        {
+            invalid = Some(LexError::InvalidSuffix {
+                offset: self.position + suffix,
+                len: end - suffix,
+            });
+        }
+
+        match invalid {
+            Some(error) => {
+                self.position += end;
+                Err(error)
             }
-            b']' => {
-                let token = Token::new(Square(BracketState::Close), &slice[..1]);
-                if let Some(Square(BracketState::Open)) = self.braces_stack.pop() {
-                    token
-                } else {
-                    panic!("{}", BracketError::UnexpectedClose(']'));
+            None => Ok(Token::new(kind, &slice[..end])),
+        }
+    }
+
+    /// The length of the block comment at the start of `slice`, including any block comments
+    /// nested inside it.
+    fn block_comment_len(&mut self, slice: &[u8]) -> Result<usize, LexError> {
+        let mut depth = 0;
+        let mut end = 0;
+
+        while end + 1 < slice.len() {
+            match &slice[end..end + 2] {
+                b"/*" => {
+                    depth += 1;
+                    end += 2;
                 }
-            }
-            b'<' => Token::new(Smaller, &slice[..1]),
-            b'>' => Token::new(Bigger, &slice[..1]),
-            b',' => Token::new(Comma, &slice[..1]),
-            b'.' => Token::new(Dot, &slice[..1]),
-            b'-' => {
-                let old = self.position;
-                self.position += 1;
-                if let Some(Token {
-                    token_type: Bigger, ..
-                }) = self.next()
-                {
-                    return Some(Token::new(Arrow, &slice[..2]));
-                } else {
-                    self.position = old;
-                    Token::new(Minus, &slice[..1])
+                b"*/" => {
+                    depth -= 1;
+                    end += 2;
+                    if depth == 0 {
+                        return Ok(end);
+                    }
                 }
+                _ => end += 1,
             }
-            b'+' => Token::new(Plus, &slice[..1]),
-            b';' => Token::new(Semicolon, &slice[..1]),
-            b'/' => Token::new(Slash, &slice[..1]),
-            b'*' => Token::new(Star, &slice[..1]),
-            b'=' => Token::new(Assign, &slice[..1]),
-            b':' => Token::new(Colon, &slice[..1]),
-            b'\0' => Token::new(Eof, &slice[..1]),
-            b'a'..=b'z' | b'A'..=b'Z' => {
-                let mut end = 1;
-                while end < slice.len() && slice[end].is_ascii_alphanumeric() {
-                    end += 1;
-                }
+        }
 
-                let token_type = match &slice[..end] {
-                    b"let" => Let,
-                    b"mut" => Mut,
-                    b"fn" => Fn,
-                    _ => Ident,
-                };
+        let offset = self.position;
+        self.position += slice.len();
+        Err(LexError::UnterminatedComment { offset })
+    }
 
-                Token::new(token_type, &slice[..end])
-            }
-            b'0'..=b'9' => {
-                let mut end = 1;
-                while end < slice.len() && slice[end].is_ascii_digit() {
-                    end += 1;
+    /// Lexes a double-quoted string, or the part of one that follows an interpolation when
+    /// `slice` starts at the `}` closing it. An unescaped `{` ends the part and starts an
+    /// interpolation, which is tracked on the mode stack until its matching `}`. The whole part
+    /// is consumed even when it contains an invalid escape, so lexing resumes after it.
+    fn lex_string(&mut self, slice: &'a [u8], string_start: usize) -> Result<Token<'a>, LexError> {
+        let resumed = slice[0] == b'}';
+        let mut end = 1;
+        let mut invalid = None;
+
+        let kind = loop {
+            match slice.get(end) {
+                None => {
+                    self.position += end;
+                    return Err(LexError::UnterminatedString {
+                        offset: string_start,
+                    });
                 }
-                Token::new(Integer, &slice[..end])
+                Some(b'"') if resumed => break StringEnd,
+                Some(b'"') => break String,
+                Some(b'{') => {
+                    self.braces_stack.push((StringStart, self.position + end));
+                    self.modes.push(Mode::Interpolation { string_start });
+                    break if resumed { StringMiddle } else { StringStart };
+                }
+                Some(b'\\') if end + 1 < slice.len() => match literal::scan_escape(&slice[end..]) {
+                    Ok((_, len)) => end += len,
+                    Err((reason, len)) => {
+                        invalid.get_or_insert(LexError::InvalidEscape {
+                            offset: self.position + end,
+                            len,
+                            reason,
+                        });
+                        end += len;
+                    }
+                },
+                Some(_) => end += 1,
             }
-            _ => panic!("Unknown token"),
         };
 
-        self.position += token.literal.len();
+        match invalid {
+            Some(error) => {
+                self.position += end + 1;
+                Err(error)
+            }
+            None => Ok(Token::new(kind, &slice[..end + 1])),
+        }
+    }
+
+    /// Lexes a raw string such as `r#"..."#`, whose contents are taken verbatim up to a quote
+    /// followed by as many `#` as the opening had.
+    fn lex_raw_string(&mut self, slice: &'a [u8]) -> Result<Token<'a>, LexError> {
+        let hashes = raw_string_hashes(slice).expect("checked by the caller");
+        let contents = hashes + 2;
 
-        Some(token)
+        let closing = slice[contents..]
+            .iter()
+            .enumerate()
+            .find_map(|(index, &byte)| {
+                let rest = &slice[contents + index + 1..];
+                let closes = byte == b'"'
+                    && rest.len() >= hashes
+                    && rest[..hashes].iter().all(|&byte| byte == b'#');
+                closes.then_some(contents + index)
+            });
+
+        match closing {
+            Some(closing) => Ok(Token::new(RawString, &slice[..closing + 1 + hashes])),
+            None => {
+                let offset = self.position;
+                self.position += slice.len();
+                Err(LexError::UnterminatedRawString { offset, hashes })
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Lexes a character literal or a label. A quote followed by an identifier is a label unless
+    /// another quote directly follows the identifier; anything else must be a single, possibly
+    /// escaped, character between quotes.
+    fn lex_quote(&mut self, slice: &'a [u8]) -> Result<Token<'a>, LexError> {
+        let offset = self.position;
+        let ident = ident_len(&slice[1..]);
 
-    fn test_lexer(input: &str, expected: Vec<TokenType>) {
-        let lexer = super::Lexer::new(input.as_bytes());
+        let end = match slice.get(1) {
+            Some(b'\\') if slice.len() > 2 => match literal::scan_escape(&slice[1..]) {
+                Ok((_, len)) => 1 + len,
+                Err((reason, len)) => {
+                    let mut end = 1 + len;
+                    if slice.get(end) == Some(&b'\'') {
+                        end += 1;
+                    }
+                    self.position += end;
+                    return Err(LexError::InvalidEscape {
+                        offset: offset + 1,
+                        len,
+                        reason,
+                    });
+                }
+            },
+            Some(b'\'') => {
+                self.position += 2;
+                return Err(LexError::EmptyChar { offset });
+            }
+            Some(_) if ident > 0 => {
+                let end = 1 + ident;
 
-        let tokens: Vec<_> = lexer.map(|token| token.token_type).collect();
+                if slice.get(end) != Some(&b'\'') {
+                    return Ok(Token::new(Label, &slice[..end]));
+                }
+                if ident > decode_char(&slice[1..]).1 {
+                    self.position += end + 1;
+                    return Err(LexError::CharTooLong { offset });
+                }
+                end
+            }
+            Some(_) => 1 + decode_char(&slice[1..]).1,
+            None => 1,
+        };
 
-        assert_eq!(tokens, expected);
-    }
+        if slice.get(end) != Some(&b'\'') {
+            self.position += end;
+            return Err(LexError::UnterminatedChar { offset });
+        }
 
-    #[test]
-    fn test_lexer_paren() {
-        test_lexer(
-            "()",
-            vec![
-                TokenType::Paren(BracketState::Open),
-                TokenType::Paren(BracketState::Close),
-            ],
-        );
+        Ok(Token::new(Char, &slice[..end + 1]))
     }
 
-    #[test]
-    fn test_lexer_brace() {
-        test_lexer(
-            "{}",
-            vec![
-                TokenType::Curly(BracketState::Open),
-                TokenType::Curly(BracketState::Close),
-            ],
-        );
+    /// Fills in the span and line/column of a token starting at `start`.
+    fn locate(&mut self, mut token: Token<'a>, start: usize) -> Token<'a> {
+        token.span = Span::in_file(self.file, start, start + token.literal.len());
+        (token.line, token.column) = self.line_col(start);
+        token
     }
 
-    #[test]
-    fn test_a_declare_a_variable() {
-        let input = r"let mut five = 5;";
+    /// Resolves `offset` to a 1-based line and column. Newlines are counted incrementally as the
+    /// lexer moves forward.
+    ///
+    /// Offsets behind what has already been scanned come from unclosed brackets reported at the
+    /// end of the input, innermost first. They are counted backwards from the last such offset,
+    /// so reporting every unclosed bracket stays linear in the size of the input.
+    fn line_col(&mut self, offset: usize) -> (usize, usize) {
+        if offset < self.scanned {
+            let (from, mut line, mut line_start) = match self.rewound {
+                (from, line, line_start) if from >= offset => (from, line, line_start),
+                _ => (self.scanned, self.line, self.line_start),
+            };
 
-        let expected = vec![
-            TokenType::Let,
-            TokenType::Mut,
-            TokenType::Ident,
-            TokenType::Assign,
-            TokenType::Integer,
-            TokenType::Semicolon,
-        ];
+            let breaks = (offset..from)
+                .filter(|&index| is_line_break(self.source, index))
+                .count();
+            if breaks > 0 {
+                line -= breaks;
+                line_start = (0..offset)
+                    .rev()
+                    .find(|&index| is_line_break(self.source, index))
+                    .map_or(0, |index| index + 1);
+            }
 
-        test_lexer(input, expected);
+            self.rewound = (offset, line, line_start);
+            return (line, offset - line_start + 1);
+        }
+
+        for index in self.scanned..offset {
+            if is_line_break(self.source, index) {
+                self.line += 1;
+                self.line_start = index + 1;
+            }
+        }
+        self.scanned = offset;
+
+        (self.line, offset - self.line_start + 1)
     }
 
-    #[test]
-    #[should_panic(expected = "Unexpected close bracket: }")]
-    fn test_panic_on_uncorrect_brackets() {
-        let input = r"let mut five = 5; }";
+    /// Leaves the innermost interpolation, returning the start of its string.
+    fn pop_interpolation(&mut self) -> usize {
+        match self.modes.pop() {
+            Some(Mode::Interpolation { string_start }) => string_start,
+            None => unreachable!("an interpolation marker is always paired with a mode"),
+        }
+    }
 
-        let expected = vec![
-            TokenType::Let,
-            TokenType::Mut,
-            TokenType::Ident,
-            TokenType::Assign,
-            TokenType::Integer,
-            TokenType::Semicolon,
-        ];
+    fn close_bracket(&mut self, kind: TokenKind, literal: &'a [u8]) -> Result<Token<'a>, LexError> {
+        let open = match kind {
+            Paren(_) => Paren(BracketState::Open),
+            Curly(_) => Curly(BracketState::Open),
+            Square(_) => Square(BracketState::Open),
+            _ => unreachable!(),
+        };
 
-        test_lexer(input, expected);
+        // Inside an interpolation, only its closing `}` may pop the marker it left on the stack.
+        let popped = match self.braces_stack.last() {
+            Some((StringStart, _)) => None,
+            _ => self.braces_stack.pop(),
+        };
+
+        match popped {
+            Some((brace, _)) if brace == open => Ok(Token::new(kind, literal)),
+            popped => {
+                let offset = self.position;
+                self.position += literal.len();
+                Err(LexError::UnexpectedClose {
+                    bracket: literal[0] as char,
+                    offset,
+                    open: popped.map(|(brace, offset)| (open_bracket_char(brace), offset)),
+                })
+            }
+        }
     }
 
-    #[test]
-    fn test_function() {
-        let input = r"fn add(x: int, y: int) -> int {
-            x + y
-        }";
+    /// Comments and literals scan across invalid UTF-8 rather than ending at it. This reports the
+    /// invalid sequences before `end`, where such a construct ends, together with the construct's
+    /// own `error` in order of offset: the first is returned and the rest are left to the
+    /// following calls.
+    fn invalid_utf8_before(&mut self, end: usize, error: Option<LexError>) -> Option<LexError> {
+        let mut errors: Vec<_> = error.into_iter().collect();
+        while let Some((offset, len)) = self.utf8_error.filter(|&(offset, _)| offset < end) {
+            errors.push(LexError::InvalidUtf8 { offset, len });
+            self.utf8_error = next_utf8_error(self.source, offset + len);
+        }
 
-        let expected = vec![
-            TokenType::Fn,
-            TokenType::Ident,
-            TokenType::Paren(BracketState::Open),
-            TokenType::Ident,
-            TokenType::Colon,
-            TokenType::Ident,
-            TokenType::Comma,
-            TokenType::Ident,
-            TokenType::Colon,
-            TokenType::Ident,
-            TokenType::Paren(BracketState::Close),
-            TokenType::Arrow,
-            TokenType::Ident,
-            TokenType::Curly(BracketState::Open),
-            TokenType::Ident,
-            TokenType::Plus,
-            TokenType::Ident,
-            TokenType::Curly(BracketState::Close),
-        ];
+        errors.sort_by_key(|error| std::cmp::Reverse(error.offset()));
+        let first = errors.pop();
+        self.pending = errors;
+        first
+    }
 
-        test_lexer(input, expected);
+    /// Checks the comment or literal lexed at the current position for invalid UTF-8, turning
+    /// the token into an error that still consumes the whole construct.
+    fn scanned_across(
+        &mut self,
+        result: Result<Token<'a>, LexError>,
+    ) -> Result<Token<'a>, LexError> {
+        let end = match &result {
+            Ok(token) => self.position + token.literal.len(),
+            Err(_) => self.position,
+        };
+
+        match self.invalid_utf8_before(end, result.err()) {
+            Some(error) => {
+                self.position = end;
+                Err(error)
+            }
+            None => result,
+        }
     }
 
-    #[test]
-    fn test_arrow() {
-        let inputs = vec!["->", "=>", "->>", "->>>", "-->"];
-        let expected = vec![
-            vec![TokenType::Arrow],
-            vec![TokenType::Assign, TokenType::Bigger],
-            vec![TokenType::Arrow, TokenType::Bigger],
-            vec![TokenType::Arrow, TokenType::Bigger, TokenType::Bigger],
-            vec![TokenType::Minus, TokenType::Arrow],
-        ];
+    /// Skips whitespace, a leading byte order mark and comments other than doc comments. This is
+    /// a loop rather than a recursive call per skipped item, so long runs of trivia cannot
+    /// overflow the stack.
+    fn skip_trivia(&mut self) -> Result<(), LexError> {
+        loop {
+            let slice = &self.source[self.position..self.limit()];
+            let full = &self.source[self.position..];
 
-        for idx in 0..inputs.len() {
-            test_lexer(inputs[idx], expected[idx].clone())
+            match slice {
+                [byte, ..] if is_whitespace(*byte) => {
+                    self.position += slice
+                        .iter()
+                        .take_while(|&&byte| is_whitespace(byte))
+                        .count();
+                }
+                _ if self.position == 0 && slice.starts_with(BOM) => self.position += BOM.len(),
+                [b'/', b'/', ..] if !is_doc_comment(full) => {
+                    self.token_start = self.position;
+                    self.position += line_comment_len(full);
+                    if let Some(error) = self.invalid_utf8_before(self.position, None) {
+                        return Err(error);
+                    }
+                }
+                [b'/', b'*', ..] => {
+                    self.token_start = self.position;
+                    let result = self.block_comment_len(full);
+                    if let Ok(len) = result {
+                        self.position += len;
+                    }
+                    if let Some(error) = self.invalid_utf8_before(self.position, result.err()) {
+                        return Err(error);
+                    }
+                }
+                _ => return Ok(()),
+            }
         }
     }
+
+    fn lex_token(&mut self) -> Result<Option<Token<'a>>, LexError> {
+        if let Some(error) = self.pending.pop() {
+            self.token_start = self.position;
+            return Err(error);
+        }
+
+        if !self.trivia {
+            self.skip_trivia()?;
+        }
+
+        if let Some((offset, len)) = self.utf8_error {
+            if self.position == offset {
+                self.token_start = offset;
+                self.position += len;
+                self.utf8_error = next_utf8_error(self.source, self.position);
+                return Err(LexError::InvalidUtf8 { offset, len });
+            }
+        }
+
+        self.token_start = self.position;
+        if self.position >= self.source.len() {
+            if let Some((brace, offset)) = self.braces_stack.pop() {
+                if brace == StringStart {
+                    return Err(LexError::UnterminatedString {
+                        offset: self.pop_interpolation(),
+                    });
+                }
+
+                return Err(LexError::UnclosedOpen {
+                    bracket: open_bracket_char(brace),
+                    offset,
+                });
+            }
+
+            return Ok(None);
+        }
+
+        let source = self.source;
+        let slice = &source[self.position..self.limit()];
+        // Comments and literals are not cut off at invalid UTF-8.
+        let full = &source[self.position..];
+
+        let token = match slice[0] {
+            b'\r' if slice.get(1) == Some(&b'\n') => Token::new(Newline, &slice[..2]),
+            b'\n' | b'\r' => Token::new(Newline, &slice[..1]),
+            byte if is_whitespace(byte) => {
+                let end = slice
+                    .iter()
+                    .take_while(|&&byte| is_whitespace(byte) && !is_newline(byte))
+                    .count();
+                Token::new(Whitespace, &slice[..end])
+            }
+            _ if self.position == 0 && slice.starts_with(BOM) => {
+                Token::new(Whitespace, &slice[..BOM.len()])
+            }
+            b'(' => {
+                let token = Token::new(Paren(BracketState::Open), &slice[..1]);
+                self.braces_stack.push((token.kind, self.position));
+                token
+            }
+            b')' => self.close_bracket(Paren(BracketState::Close), &slice[..1])?,
+            b'{' => {
+                let token = Token::new(Curly(BracketState::Open), &slice[..1]);
+                self.braces_stack.push((token.kind, self.position));
+                token
+            }
+            b'}' if matches!(self.braces_stack.last(), Some((StringStart, _))) => {
+                self.braces_stack.pop();
+                let string_start = self.pop_interpolation();
+                let result = self.lex_string(full, string_start);
+                self.scanned_across(result)?
+            }
+            b'}' => self.close_bracket(Curly(BracketState::Close), &slice[..1])?,
+            b'[' => {
+                let token = Token::new(Square(BracketState::Open), &slice[..1]);
+                self.braces_stack.push((token.kind, self.position));
+                token
+            }
+            b']' => self.close_bracket(Square(BracketState::Close), &slice[..1])?,
+            b'/' if slice.get(1) == Some(&b'/') => {
+                let end = line_comment_len(full);
+                let kind = if is_doc_comment(full) {
+                    DocComment
+                } else {
+                    Comment
+                };
+                self.scanned_across(Ok(Token::new(kind, &full[..end])))?
+            }
+            b'/' if slice.get(1) == Some(&b'*') => {
+                let result = self
+                    .block_comment_len(full)
+                    .map(|end| Token::new(Comment, &full[..end]));
+                self.scanned_across(result)?
+            }
+            b'\0' => Token::new(Eof, &slice[..1]),
+            b'r' if raw_string_hashes(slice).is_some() => {
+                let result = self.lex_raw_string(full);
+                self.scanned_across(result)?
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' | 0x80..=0xFF => {
+                let end = ident_len(slice);
+                if end == 0 {
+                    return Err(self.unknown_character(slice));
+                }
+
+                match keyword::lookup(&slice[..end]) {
+                    Some(kind) => Token::new(kind, &slice[..end]),
+                    None => {
+                        let mut token = Token::new(Ident, &slice[..end]);
+                        token.contextual = keyword::lookup_contextual(&slice[..end]);
+                        token
+                    }
+                }
+            }
+            b'0'..=b'9' => self.lex_number(slice)?,
+            b'"' => {
+                let result = self.lex_string(full, self.position);
+                self.scanned_across(result)?
+            }
+            b'\'' => {
+                let result = self.lex_quote(full);
+                self.scanned_across(result)?
+            }
+            _ => match self.match_operator() {
+                Some((operator, kind)) => Token::new(kind, &slice[..operator.len()]),
+                None => return Err(self.unknown_character(slice)),
+            },
+        };
+
+        let start = self.position;
+        self.position += token.literal.len();
+
+        Ok(Some(self.locate(token, start)))
+    }
+}
+
+/// Every operator and punctuation token, ordered so that longer operators come before their
+/// prefixes and the first match is the longest one.
+const OPERATORS: [(&[u8], TokenKind); 35] = [
+    (b"..=", DotDotEqual),
+    (b"->", Arrow),
+    (b"=>", FatArrow),
+    (b"==", Equal),
+    (b"!=", NotEqual),
+    (b"<=", SmallerEqual),
+    (b">=", BiggerEqual),
+    (b"&&", AndAnd),
+    (b"||", OrOr),
+    (b"+=", PlusAssign),
+    (b"-=", MinusAssign),
+    (b"*=", StarAssign),
+    (b"/=", SlashAssign),
+    (b"::", DoubleColon),
+    (b"..", DotDot),
+    (b"<<", ShiftLeft),
+    (b">>", ShiftRight),
+    (b"<", Smaller),
+    (b">", Bigger),
+    (b",", Comma),
+    (b".", Dot),
+    (b"-", Minus),
+    (b"+", Plus),
+    (b";", Semicolon),
+    (b"/", Slash),
+    (b"*", Star),
+    (b"=", Assign),
+    (b":", Colon),
+    (b"!", Bang),
+    (b"&", Ampersand),
+    (b"|", Pipe),
+    (b"%", Percent),
+    (b"^", Caret),
+    (b"?", Question),
+    (b"@", At),
+];
+
+fn open_bracket_char(brace: TokenKind) -> char {
+    match brace {
+        Paren(_) => '(',
+        Curly(_) => '{',
+        Square(_) => '[',
+        _ => unreachable!(),
+    }
+}
+
+/// The UTF-8 byte order mark, skipped at the very start of the source.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Space, tab, form feed, vertical tab and the line terminators `\n` and `\r`.
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r' | b'\x0B' | b'\x0C')
+}
+
+fn is_newline(byte: u8) -> bool {
+    byte == b'\n' || byte == b'\r'
+}
+
+/// Whether the byte at `index` ends a line: a `\n`, or a `\r` not followed by `\n`, so that
+/// `\r\n` counts as a single break.
+pub(crate) fn is_line_break(source: &[u8], index: usize) -> bool {
+    match source[index] {
+        b'\n' => true,
+        b'\r' => source.get(index + 1) != Some(&b'\n'),
+        _ => false,
+    }
+}
+
+/// The length of the line comment at the start of `slice`, excluding its line terminator.
+fn line_comment_len(slice: &[u8]) -> usize {
+    slice
+        .iter()
+        .position(|&byte| is_newline(byte))
+        .unwrap_or(slice.len())
+}
+
+/// Whether the line comment at the start of `slice` is a `///` or `//!` doc comment. Comments
+/// starting with four or more slashes are ordinary comments.
+fn is_doc_comment(slice: &[u8]) -> bool {
+    match slice.get(2) {
+        Some(b'!') => true,
+        Some(b'/') => slice.get(3) != Some(&b'/'),
+        _ => false,
+    }
+}
+
+/// The number of `#` in the opening delimiter of the raw string at the start of `slice`, which
+/// must start with `r`, or `None` if `slice` does not start a raw string.
+fn raw_string_hashes(slice: &[u8]) -> Option<usize> {
+    let hashes = slice[1..].iter().take_while(|&&byte| byte == b'#').count();
+    (slice.get(1 + hashes) == Some(&b'"')).then_some(hashes)
+}
+
+/// The location and length of the first invalid UTF-8 sequence at or after `from`.
+fn next_utf8_error(source: &[u8], from: usize) -> Option<(usize, usize)> {
+    let error = std::str::from_utf8(&source[from..]).err()?;
+    let offset = from + error.valid_up_to();
+    let len = error.error_len().unwrap_or(source.len() - offset);
+    Some((offset, len))
+}
+
+/// The length of the identifier at the start of `slice`, or 0 if it does not start with one.
+/// Identifiers follow UAX #31: an `XID_Start` character or `_`, then `XID_Continue` characters.
+fn ident_len(slice: &[u8]) -> usize {
+    let mut end = 0;
+
+    while end < slice.len() {
+        let byte = slice[end];
+        let (is_ident, len) = if byte.is_ascii() {
+            let is_ident = match end {
+                0 => byte.is_ascii_alphabetic() || byte == b'_',
+                _ => byte.is_ascii_alphanumeric() || byte == b'_',
+            };
+            (is_ident, 1)
+        } else {
+            let (character, len) = decode_char(&slice[end..]);
+            let is_ident = match end {
+                0 => unicode::is_ident_start(character),
+                _ => unicode::is_ident_continue(character),
+            };
+            (is_ident, len)
+        };
+
+        if !is_ident {
+            break;
+        }
+        end += len;
+    }
+
+    end
+}
+
+/// Decodes the character at the start of `slice`, falling back to U+FFFD and the length of the
+/// invalid sequence when the bytes are not valid UTF-8.
+pub(crate) fn decode_char(slice: &[u8]) -> (char, usize) {
+    let chunk = slice[..slice.len().min(4)]
+        .utf8_chunks()
+        .next()
+        .expect("slice is not empty");
+
+    match chunk.valid().chars().next() {
+        Some(character) => (character, character.len_utf8()),
+        None => (char::REPLACEMENT_CHARACTER, chunk.invalid().len()),
+    }
+}
+
+use TokenKind::*;
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+            .map(|token| token.unwrap_or_else(|error| panic!("{}", error)))
+    }
+}
+
+pub struct TryIter<'a> {
+    lexer: Lexer<'a>,
+}
+
+impl<'a> Iterator for TryIter<'a> {
+    type Item = Result<Token<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lexer.next_token()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_lexer(input: &str, expected: Vec<TokenKind>) {
+        let lexer = super::Lexer::new(input.as_bytes());
+
+        let tokens: Vec<_> = lexer.map(|token| token.kind).collect();
+
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_lexer_paren() {
+        test_lexer(
+            "()",
+            vec![
+                TokenKind::Paren(BracketState::Open),
+                TokenKind::Paren(BracketState::Close),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lexer_brace() {
+        test_lexer(
+            "{}",
+            vec![
+                TokenKind::Curly(BracketState::Open),
+                TokenKind::Curly(BracketState::Close),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_a_declare_a_variable() {
+        let input = r"let mut five = 5;";
+
+        let expected = vec![
+            TokenKind::Let,
+            TokenKind::Mut,
+            TokenKind::Ident,
+            TokenKind::Assign,
+            TokenKind::Integer,
+            TokenKind::Semicolon,
+        ];
+
+        test_lexer(input, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected close bracket: }")]
+    fn test_panic_on_uncorrect_brackets() {
+        let input = r"let mut five = 5; }";
+
+        let expected = vec![
+            TokenKind::Let,
+            TokenKind::Mut,
+            TokenKind::Ident,
+            TokenKind::Assign,
+            TokenKind::Integer,
+            TokenKind::Semicolon,
+        ];
+
+        test_lexer(input, expected);
+    }
+
+    #[test]
+    fn test_function() {
+        let input = r"fn add(x: int, y: int) -> int {
+            x + y
+        }";
+
+        let expected = vec![
+            TokenKind::Fn,
+            TokenKind::Ident,
+            TokenKind::Paren(BracketState::Open),
+            TokenKind::Ident,
+            TokenKind::Colon,
+            TokenKind::Ident,
+            TokenKind::Comma,
+            TokenKind::Ident,
+            TokenKind::Colon,
+            TokenKind::Ident,
+            TokenKind::Paren(BracketState::Close),
+            TokenKind::Arrow,
+            TokenKind::Ident,
+            TokenKind::Curly(BracketState::Open),
+            TokenKind::Ident,
+            TokenKind::Plus,
+            TokenKind::Ident,
+            TokenKind::Curly(BracketState::Close),
+        ];
+
+        test_lexer(input, expected);
+    }
+
+    #[test]
+    fn test_arrow() {
+        let inputs = ["->", "=>", "->>", "->>>", "-->"];
+        let expected = [
+            vec![TokenKind::Arrow],
+            vec![TokenKind::FatArrow],
+            vec![TokenKind::Arrow, TokenKind::Bigger],
+            vec![TokenKind::Arrow, TokenKind::ShiftRight],
+            vec![TokenKind::Minus, TokenKind::Arrow],
+        ];
+
+        for idx in 0..inputs.len() {
+            test_lexer(inputs[idx], expected[idx].clone())
+        }
+    }
+
+    fn try_lex(input: &str) -> Vec<Result<TokenKind, LexError>> {
+        try_lex_bytes(input.as_bytes())
+    }
+
+    fn try_lex_bytes(input: &[u8]) -> Vec<Result<TokenKind, LexError>> {
+        super::Lexer::new(input)
+            .try_iter()
+            .map(|token| token.map(|token| token.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_try_iter_unexpected_close() {
+        assert_eq!(
+            try_lex("a } b"),
+            vec![
+                Ok(TokenKind::Ident),
+                Err(LexError::UnexpectedClose {
+                    bracket: '}',
+                    offset: 2,
+                    open: None,
+                }),
+                Ok(TokenKind::Ident),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_try_iter_unknown_character() {
+        assert_eq!(
+            try_lex("a $ € b"),
+            vec![
+                Ok(TokenKind::Ident),
+                Err(LexError::UnknownCharacter {
+                    character: '$',
+                    offset: 2
+                }),
+                Err(LexError::UnknownCharacter {
+                    character: '€',
+                    offset: 4
+                }),
+                Ok(TokenKind::Ident),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_try_iter_unclosed_open() {
+        assert_eq!(
+            try_lex("( [ ]"),
+            vec![
+                Ok(TokenKind::Paren(BracketState::Open)),
+                Ok(TokenKind::Square(BracketState::Open)),
+                Ok(TokenKind::Square(BracketState::Close)),
+                Err(LexError::UnclosedOpen {
+                    bracket: '(',
+                    offset: 0
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recovery_emits_error_tokens() {
+        let mut lexer = super::Lexer::new("let } x = $5; (".as_bytes()).with_recovery();
+
+        let tokens: Vec<_> = lexer
+            .by_ref()
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Let, &b"let"[..]),
+                (TokenKind::Error, b"}"),
+                (TokenKind::Ident, b"x"),
+                (TokenKind::Assign, b"="),
+                (TokenKind::Error, b"$"),
+                (TokenKind::Integer, b"5"),
+                (TokenKind::Semicolon, b";"),
+                (TokenKind::Paren(BracketState::Open), b"("),
+                (TokenKind::Error, b"("),
+            ]
+        );
+
+        assert_eq!(
+            lexer.diagnostics(),
+            [
+                Diagnostic {
+                    error: LexError::UnexpectedClose {
+                        bracket: '}',
+                        offset: 4,
+                        open: None,
+                    },
+                    span: Span::new(4, 5),
+                },
+                Diagnostic {
+                    error: LexError::UnknownCharacter {
+                        character: '$',
+                        offset: 10
+                    },
+                    span: Span::new(10, 11),
+                },
+                Diagnostic {
+                    error: LexError::UnclosedOpen {
+                        bracket: '(',
+                        offset: 14
+                    },
+                    span: Span::new(14, 15),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans_and_positions() {
+        let input = "fn add(x: int) {\n    x\n}";
+
+        let tokens: Vec<_> = super::Lexer::new(input.as_bytes())
+            .map(|token| (token.span(), token.line(), token.column()))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (Span::new(0, 2), 1, 1),
+                (Span::new(3, 6), 1, 4),
+                (Span::new(6, 7), 1, 7),
+                (Span::new(7, 8), 1, 8),
+                (Span::new(8, 9), 1, 9),
+                (Span::new(10, 13), 1, 11),
+                (Span::new(13, 14), 1, 14),
+                (Span::new(15, 16), 1, 16),
+                (Span::new(21, 22), 2, 5),
+                (Span::new(23, 24), 3, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_span_matches_literal() {
+        let input = "let mut five = 5 -> ( ) [ ]";
+
+        for token in super::Lexer::new(input.as_bytes()) {
+            assert_eq!(
+                &input.as_bytes()[token.span().start..token.span().end],
+                token.literal
+            );
+        }
+    }
+
+    #[test]
+    fn test_unclosed_error_position() {
+        let mut lexer = super::Lexer::new("x\n  (\n y".as_bytes()).with_recovery();
+
+        let errors: Vec<_> = lexer
+            .by_ref()
+            .filter(|token| token.kind == TokenKind::Error)
+            .map(|token| (token.line(), token.column()))
+            .collect();
+
+        assert_eq!(errors, vec![(2, 3)]);
+    }
+
+    #[test]
+    fn test_mismatched_close_reports_popped_open() {
+        assert_eq!(
+            try_lex("[ ( ]"),
+            vec![
+                Ok(TokenKind::Square(BracketState::Open)),
+                Ok(TokenKind::Paren(BracketState::Open)),
+                Err(LexError::UnexpectedClose {
+                    bracket: ']',
+                    offset: 4,
+                    open: Some(('(', 2)),
+                }),
+                Err(LexError::UnclosedOpen {
+                    bracket: '[',
+                    offset: 0
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        test_lexer(
+            r#"let s = "hello, world\n"; "a\"b" "\x41\u{1F600}\0\\""#,
+            vec![
+                TokenKind::Let,
+                TokenKind::Ident,
+                TokenKind::Assign,
+                TokenKind::String,
+                TokenKind::Semicolon,
+                TokenKind::String,
+                TokenKind::String,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        assert_eq!(
+            try_lex(r#"x "abc\""#),
+            vec![
+                Ok(TokenKind::Ident),
+                Err(LexError::UnterminatedString { offset: 2 })
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        let cases = [
+            (r#""\q""#, 1, 2, EscapeError::Unknown('q')),
+            (r#""ab\x4""#, 3, 3, EscapeError::MalformedHex),
+            (r#""\x80""#, 1, 4, EscapeError::HexOutOfRange),
+            (r#""\u41""#, 1, 2, EscapeError::MalformedUnicode),
+            (r#""\u{}""#, 1, 4, EscapeError::MalformedUnicode),
+            (r#""\u{1234567}""#, 1, 11, EscapeError::MalformedUnicode),
+            (r#""\u{D800}""#, 1, 8, EscapeError::InvalidCodepoint),
+            (r#""\u{110000}""#, 1, 10, EscapeError::InvalidCodepoint),
+        ];
+
+        for (input, offset, len, reason) in cases {
+            assert_eq!(
+                try_lex(input),
+                vec![Err(LexError::InvalidEscape {
+                    offset,
+                    len,
+                    reason
+                })],
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_escape_recovery_skips_whole_string() {
+        let mut lexer = super::Lexer::new(r#""a\qb" x"#.as_bytes()).with_recovery();
+
+        let tokens: Vec<_> = lexer
+            .by_ref()
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Error, &br#""a\qb""#[..]),
+                (TokenKind::Ident, b"x")
+            ]
+        );
+        assert_eq!(lexer.diagnostics()[0].span, Span::new(2, 4));
+    }
+
+    #[test]
+    fn test_chars_and_labels() {
+        test_lexer(
+            r"'a' '\n' '\u{1F600}' 'é' '\'' 'outer loop 'x",
+            vec![
+                TokenKind::Char,
+                TokenKind::Char,
+                TokenKind::Char,
+                TokenKind::Char,
+                TokenKind::Char,
+                TokenKind::Label,
+                TokenKind::Loop,
+                TokenKind::Label,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_invalid_chars() {
+        assert_eq!(
+            try_lex(r"'ab' '' '\q' x '+"),
+            vec![
+                Err(LexError::CharTooLong { offset: 0 }),
+                Err(LexError::EmptyChar { offset: 5 }),
+                Err(LexError::InvalidEscape {
+                    offset: 9,
+                    len: 2,
+                    reason: EscapeError::Unknown('q')
+                }),
+                Ok(TokenKind::Ident),
+                Err(LexError::UnterminatedChar { offset: 15 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_floats() {
+        test_lexer(
+            "3.14 1e10 1e-9 2.5E+3 0.5e2",
+            vec![
+                TokenKind::Float,
+                TokenKind::Float,
+                TokenKind::Float,
+                TokenKind::Float,
+                TokenKind::Float,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_float_ambiguities() {
+        test_lexer(
+            "1..2 x.0.1 1.foo",
+            vec![
+                TokenKind::Integer,
+                TokenKind::DotDot,
+                TokenKind::Integer,
+                TokenKind::Ident,
+                TokenKind::Dot,
+                TokenKind::Integer,
+                TokenKind::Dot,
+                TokenKind::Integer,
+                TokenKind::Integer,
+                TokenKind::Dot,
+                TokenKind::Ident,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_integer_radixes_and_suffixes() {
+        test_lexer(
+            "0xFF 0o755 0b1010 1_000_000 5u8 10i64 0xffu8 0b1_0usize 1_000.5",
+            vec![
+                TokenKind::Integer,
+                TokenKind::Integer,
+                TokenKind::Integer,
+                TokenKind::Integer,
+                TokenKind::Integer,
+                TokenKind::Integer,
+                TokenKind::Integer,
+                TokenKind::Integer,
+                TokenKind::Float,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_invalid_integers() {
+        assert_eq!(
+            try_lex("0b2 0o178 0x 0b_ 5u7 1e 2.5u8 x"),
+            vec![
+                Err(LexError::InvalidDigit {
+                    offset: 2,
+                    radix: 2
+                }),
+                Err(LexError::InvalidDigit {
+                    offset: 8,
+                    radix: 8
+                }),
+                Err(LexError::MissingDigits { offset: 10 }),
+                Err(LexError::MissingDigits { offset: 13 }),
+                Err(LexError::InvalidSuffix { offset: 18, len: 2 }),
+                Err(LexError::InvalidSuffix { offset: 22, len: 1 }),
+                Err(LexError::InvalidSuffix { offset: 27, len: 2 }),
+                Ok(TokenKind::Ident),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        test_lexer(
+            "let x = 5; // the answer / 2\n/* a /* nested */ comment */ x /**/ / 2 //",
+            vec![
+                TokenKind::Let,
+                TokenKind::Ident,
+                TokenKind::Assign,
+                TokenKind::Integer,
+                TokenKind::Semicolon,
+                TokenKind::Ident,
+                TokenKind::Slash,
+                TokenKind::Integer,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let input = "//! Module docs\n/// Adds two numbers\n//// not docs\nfn add";
+
+        let tokens: Vec<_> = super::Lexer::new(input.as_bytes())
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::DocComment, &b"//! Module docs"[..]),
+                (TokenKind::DocComment, b"/// Adds two numbers"),
+                (TokenKind::Fn, b"fn"),
+                (TokenKind::Ident, b"add"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        assert_eq!(
+            try_lex("x /* a /* b */ c"),
+            vec![
+                Ok(TokenKind::Ident),
+                Err(LexError::UnterminatedComment { offset: 2 })
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operators_longest_match() {
+        test_lexer(
+            "== != <= >= && || += -= *= /= => :: .. ..= << >> ! & | % ^ ? @ = < > : . ...",
+            vec![
+                TokenKind::Equal,
+                TokenKind::NotEqual,
+                TokenKind::SmallerEqual,
+                TokenKind::BiggerEqual,
+                TokenKind::AndAnd,
+                TokenKind::OrOr,
+                TokenKind::PlusAssign,
+                TokenKind::MinusAssign,
+                TokenKind::StarAssign,
+                TokenKind::SlashAssign,
+                TokenKind::FatArrow,
+                TokenKind::DoubleColon,
+                TokenKind::DotDot,
+                TokenKind::DotDotEqual,
+                TokenKind::ShiftLeft,
+                TokenKind::ShiftRight,
+                TokenKind::Bang,
+                TokenKind::Ampersand,
+                TokenKind::Pipe,
+                TokenKind::Percent,
+                TokenKind::Caret,
+                TokenKind::Question,
+                TokenKind::At,
+                TokenKind::Assign,
+                TokenKind::Smaller,
+                TokenKind::Bigger,
+                TokenKind::Colon,
+                TokenKind::Dot,
+                TokenKind::DotDot,
+                TokenKind::Dot,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_operators_without_spaces() {
+        test_lexer(
+            "a<=b&&!c||d<<=1..=2",
+            vec![
+                TokenKind::Ident,
+                TokenKind::SmallerEqual,
+                TokenKind::Ident,
+                TokenKind::AndAnd,
+                TokenKind::Bang,
+                TokenKind::Ident,
+                TokenKind::OrOr,
+                TokenKind::Ident,
+                TokenKind::ShiftLeft,
+                TokenKind::Assign,
+                TokenKind::Integer,
+                TokenKind::DotDotEqual,
+                TokenKind::Integer,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_arrow_lookahead_keeps_bracket_state() {
+        test_lexer(
+            "-() - > -{} -[]",
+            vec![
+                TokenKind::Minus,
+                TokenKind::Paren(BracketState::Open),
+                TokenKind::Paren(BracketState::Close),
+                TokenKind::Minus,
+                TokenKind::Bigger,
+                TokenKind::Minus,
+                TokenKind::Curly(BracketState::Open),
+                TokenKind::Curly(BracketState::Close),
+                TokenKind::Minus,
+                TokenKind::Square(BracketState::Open),
+                TokenKind::Square(BracketState::Close),
+            ],
+        );
+
+        assert_eq!(
+            try_lex("{ -}"),
+            vec![
+                Ok(TokenKind::Curly(BracketState::Open)),
+                Ok(TokenKind::Minus),
+                Ok(TokenKind::Curly(BracketState::Close)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spaced_arrow_is_not_an_arrow() {
+        let tokens: Vec<_> = super::Lexer::new(b"- >")
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![(TokenKind::Minus, &b"-"[..]), (TokenKind::Bigger, b">")]
+        );
+    }
+
+    #[test]
+    fn test_peek_does_not_consume() {
+        let mut lexer = super::Lexer::new(b"( x -> ) }");
+
+        assert_eq!(lexer.peek_byte(0), Some(b'('));
+        assert_eq!(lexer.peek_byte(100), None);
+
+        let mut tokens = Vec::new();
+        while let Some(peeked) = lexer.peek_token() {
+            assert_eq!(lexer.peek_token(), Some(peeked));
+            let next = lexer.next_token().unwrap();
+            assert_eq!(peeked, next);
+            tokens.push(next.map(|token| token.kind));
+        }
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(TokenKind::Paren(BracketState::Open)),
+                Ok(TokenKind::Ident),
+                Ok(TokenKind::Arrow),
+                Ok(TokenKind::Paren(BracketState::Close)),
+                Err(LexError::UnexpectedClose {
+                    bracket: '}',
+                    offset: 9,
+                    open: None
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_peek_unclosed_at_end() {
+        let mut lexer = super::Lexer::new(b"[(").with_recovery();
+        lexer.by_ref().take(2).for_each(drop);
+
+        let peeked = lexer.peek_token().unwrap().unwrap();
+        assert_eq!(peeked.literal, b"(");
+        assert!(lexer.diagnostics().is_empty());
+
+        assert_eq!(lexer.next_token(), Some(Ok(peeked)));
+        assert_eq!(lexer.next().map(|token| token.literal), Some(&b"["[..]));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn test_keywords() {
+        test_lexer(
+            "let mut fn if else while for in loop break continue return true false struct enum \
+             match impl pub use const type",
+            vec![
+                TokenKind::Let,
+                TokenKind::Mut,
+                TokenKind::Fn,
+                TokenKind::If,
+                TokenKind::Else,
+                TokenKind::While,
+                TokenKind::For,
+                TokenKind::In,
+                TokenKind::Loop,
+                TokenKind::Break,
+                TokenKind::Continue,
+                TokenKind::Return,
+                TokenKind::True,
+                TokenKind::False,
+                TokenKind::Struct,
+                TokenKind::Enum,
+                TokenKind::Match,
+                TokenKind::Impl,
+                TokenKind::Pub,
+                TokenKind::Use,
+                TokenKind::Const,
+                TokenKind::Type,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_contextual_keywords_are_identifiers() {
+        let tokens: Vec<_> = super::Lexer::new(b"union async default unions let")
+            .map(|token| (token.kind, token.contextual()))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Ident, Some(Kw::Union)),
+                (TokenKind::Ident, Some(Kw::Async)),
+                (TokenKind::Ident, Some(Kw::Default)),
+                (TokenKind::Ident, None),
+                (TokenKind::Let, None),
+            ]
+        );
+
+        let union = super::Lexer::new(b"union").next().unwrap();
+        assert!(union.is_contextual(Kw::Union));
+        assert!(!union.is_contextual(Kw::Default));
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let tokens: Vec<_> =
+            super::Lexer::new("_private snake_case café x́ 中文 Ωmega2 'étiquette _".as_bytes())
+                .map(|token| (token.kind, token.literal))
+                .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Ident, "_private".as_bytes()),
+                (TokenKind::Ident, "snake_case".as_bytes()),
+                (TokenKind::Ident, "café".as_bytes()),
+                (TokenKind::Ident, "x\u{301}".as_bytes()),
+                (TokenKind::Ident, "中文".as_bytes()),
+                (TokenKind::Ident, "Ωmega2".as_bytes()),
+                (TokenKind::Label, "'étiquette".as_bytes()),
+                (TokenKind::Ident, "_".as_bytes()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_identifier_characters() {
+        assert_eq!(
+            try_lex("a😀 \u{301}b"),
+            vec![
+                Ok(TokenKind::Ident),
+                Err(LexError::UnknownCharacter {
+                    character: '😀',
+                    offset: 1
+                }),
+                Err(LexError::UnknownCharacter {
+                    character: '\u{301}',
+                    offset: 6
+                }),
+                Ok(TokenKind::Ident),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let tokens: Vec<_> = super::Lexer::new(b"ab \xFF\xFEcd \"x\xC3\" \xE2\x82")
+            .try_iter()
+            .map(|token| token.map(|token| (token.kind, token.literal)))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ok((TokenKind::Ident, &b"ab"[..])),
+                Err(LexError::InvalidUtf8 { offset: 3, len: 1 }),
+                Err(LexError::InvalidUtf8 { offset: 4, len: 1 }),
+                Ok((TokenKind::Ident, b"cd")),
+                Err(LexError::InvalidUtf8 { offset: 10, len: 1 }),
+                Err(LexError::InvalidUtf8 { offset: 13, len: 2 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        let input = r###"r"C:\path" r#"say "hi""# r##"a "# b"## r rust r#x"###;
+
+        let tokens: Vec<_> = super::Lexer::new(input.as_bytes())
+            .try_iter()
+            .map(|token| token.map(|token| (token.kind, token.literal)))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ok((TokenKind::RawString, &br#"r"C:\path""#[..])),
+                Ok((TokenKind::RawString, &br##"r#"say "hi""#"##[..])),
+                Ok((TokenKind::RawString, &br###"r##"a "# b"##"###[..])),
+                Ok((TokenKind::Ident, b"r")),
+                Ok((TokenKind::Ident, b"rust")),
+                Ok((TokenKind::Ident, b"r")),
+                Err(LexError::UnknownCharacter {
+                    character: '#',
+                    offset: 47
+                }),
+                Ok((TokenKind::Ident, b"x")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_raw_string() {
+        assert_eq!(
+            try_lex(r###"x r##"abc"# "###),
+            vec![
+                Ok(TokenKind::Ident),
+                Err(LexError::UnterminatedRawString {
+                    offset: 2,
+                    hashes: 2
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let input = r#""hello {name}!" "{a} and {b + 1}" "{ {x} }""#;
+
+        let tokens: Vec<_> = super::Lexer::new(input.as_bytes())
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StringStart, &br#""hello {"#[..]),
+                (TokenKind::Ident, b"name"),
+                (TokenKind::StringEnd, br#"}!""#),
+                (TokenKind::StringStart, br#""{"#),
+                (TokenKind::Ident, b"a"),
+                (TokenKind::StringMiddle, b"} and {"),
+                (TokenKind::Ident, b"b"),
+                (TokenKind::Plus, b"+"),
+                (TokenKind::Integer, b"1"),
+                (TokenKind::StringEnd, br#"}""#),
+                (TokenKind::StringStart, br#""{"#),
+                (TokenKind::Curly(BracketState::Open), b"{"),
+                (TokenKind::Ident, b"x"),
+                (TokenKind::Curly(BracketState::Close), b"}"),
+                (TokenKind::StringEnd, br#"}""#),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_string_interpolation() {
+        test_lexer(
+            r#""a {f("b {x}", "\{c\}")} d""#,
+            vec![
+                TokenKind::StringStart,
+                TokenKind::Ident,
+                TokenKind::Paren(BracketState::Open),
+                TokenKind::StringStart,
+                TokenKind::Ident,
+                TokenKind::StringEnd,
+                TokenKind::Comma,
+                TokenKind::String,
+                TokenKind::Paren(BracketState::Close),
+                TokenKind::StringEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_interpolation_errors() {
+        assert_eq!(
+            try_lex(r#""a {)} b" ("{x"#),
+            vec![
+                Ok(TokenKind::StringStart),
+                Err(LexError::UnexpectedClose {
+                    bracket: ')',
+                    offset: 4,
+                    open: None
+                }),
+                Ok(TokenKind::StringEnd),
+                Ok(TokenKind::Paren(BracketState::Open)),
+                Ok(TokenKind::StringStart),
+                Ok(TokenKind::Ident),
+                Err(LexError::UnterminatedString { offset: 11 }),
+                Err(LexError::UnclosedOpen {
+                    bracket: '(',
+                    offset: 10
+                }),
+            ]
+        );
+
+        assert_eq!(
+            try_lex(r#""{x} tail"#),
+            vec![
+                Ok(TokenKind::StringStart),
+                Ok(TokenKind::Ident),
+                Err(LexError::UnterminatedString { offset: 0 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_interpolation_recovery() {
+        let mut lexer = super::Lexer::new(br#"x "{y"#).with_recovery();
+
+        let tokens: Vec<_> = lexer
+            .by_ref()
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Ident, &b"x"[..]),
+                (TokenKind::StringStart, br#""{"#),
+                (TokenKind::Ident, b"y"),
+                (TokenKind::Error, b""),
+            ]
+        );
+        assert_eq!(lexer.diagnostics()[0].span, Span::new(2, 3));
+    }
+
+    fn concat_trivia(input: &[u8]) -> Vec<u8> {
+        super::Lexer::new(input)
+            .with_recovery()
+            .with_trivia()
+            .flat_map(|token| token.literal.iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn test_trivia_tokens() {
+        let input = "let x = 1; // one\n\t/* two */ x\n";
+
+        let tokens: Vec<_> = super::Lexer::new(input.as_bytes())
+            .with_trivia()
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Let, &b"let"[..]),
+                (TokenKind::Whitespace, b" "),
+                (TokenKind::Ident, b"x"),
+                (TokenKind::Whitespace, b" "),
+                (TokenKind::Assign, b"="),
+                (TokenKind::Whitespace, b" "),
+                (TokenKind::Integer, b"1"),
+                (TokenKind::Semicolon, b";"),
+                (TokenKind::Whitespace, b" "),
+                (TokenKind::Comment, b"// one"),
+                (TokenKind::Newline, b"\n"),
+                (TokenKind::Whitespace, b"\t"),
+                (TokenKind::Comment, b"/* two */"),
+                (TokenKind::Whitespace, b" "),
+                (TokenKind::Ident, b"x"),
+                (TokenKind::Newline, b"\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trivia_reproduces_input() {
+        let inputs: [&[u8]; 6] = [
+            b"fn add(x: int, y: int) -> int {\n    x + y // sum\n}\n",
+            b"  /* a /* nested */ b */ 'label: loop { break 'label; }",
+            b"let s = \"a {b + \"{c}\"} d\\n\"; r#\"raw\"# 'c' 0xFFu8 1.5e3",
+            b"( [ } $ \xFF\xFE \"unterminated",
+            b"/* unterminated",
+            b"\"{x",
+        ];
+
+        for input in inputs {
+            assert_eq!(
+                concat_trivia(input),
+                input,
+                "{}",
+                std::string::String::from_utf8_lossy(input)
+            );
+        }
+    }
+
+    #[test]
+    fn test_trivia_reproduces_random_input() {
+        const FRAGMENTS: [&[u8]; 42] = [
+            b" ",
+            b"  ",
+            b"\t",
+            b"\n",
+            b"\r",
+            b"\r\n",
+            b"\x0C",
+            b"\0",
+            b"x",
+            b"_y2",
+            b"let",
+            b"fn",
+            b"mut",
+            b"caf\xC3\xA9",
+            b"\xFF",
+            b"\xE2\x82",
+            b"0",
+            b"42",
+            b"0x1F",
+            b"0b102",
+            b"1.5e",
+            b"7u8",
+            b"\"",
+            b"\\",
+            b"{",
+            b"}",
+            b"(",
+            b")",
+            b"[",
+            b"]",
+            b"'",
+            b"'a'",
+            b"'lbl",
+            b"r#\"",
+            b"\"#",
+            b"//",
+            b"///",
+            b"/*",
+            b"*/",
+            b"->",
+            b"..=",
+            b"$",
+        ];
+
+        // A xorshift generator keeps the inputs reproducible without extra dependencies.
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..2000 {
+            let len = next() % 40;
+            let input: Vec<u8> = (0..len)
+                .flat_map(|_| {
+                    FRAGMENTS[(next() % FRAGMENTS.len() as u64) as usize]
+                        .iter()
+                        .copied()
+                })
+                .collect();
+
+            assert_eq!(
+                concat_trivia(&input),
+                input,
+                "{}",
+                std::string::String::from_utf8_lossy(&input)
+            );
+        }
+    }
+
+    #[test]
+    fn test_huge_whitespace_does_not_overflow() {
+        let input = " \t\n".repeat(2 * 1024 * 1024);
+
+        assert_eq!(super::Lexer::new(input.as_bytes()).count(), 0);
+        assert_eq!(
+            super::Lexer::new(input.as_bytes()).with_trivia().count(),
+            2 * 1024 * 1024 * 2
+        );
+
+        let input = format!("{}x", " ".repeat(4 * 1024 * 1024));
+        let tokens: Vec<_> = super::Lexer::new(input.as_bytes()).collect();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].span().start, 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_huge_comment_runs_do_not_overflow() {
+        let input = "// line\n/* block */ ".repeat(256 * 1024);
+
+        assert_eq!(super::Lexer::new(input.as_bytes()).count(), 0);
+    }
+
+    #[test]
+    fn test_windows_line_endings() {
+        test_lexer(
+            "let x = 1;\r\nlet y\r= 2;\x0C\x0B\r\n",
+            vec![
+                TokenKind::Let,
+                TokenKind::Ident,
+                TokenKind::Assign,
+                TokenKind::Integer,
+                TokenKind::Semicolon,
+                TokenKind::Let,
+                TokenKind::Ident,
+                TokenKind::Assign,
+                TokenKind::Integer,
+                TokenKind::Semicolon,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_crlf_positions() {
+        let input = "a\r\nb\rc\n\r\nd";
+        let positions: Vec<_> = super::Lexer::new(input.as_bytes())
+            .map(|token| (token.line(), token.column()))
+            .collect();
+
+        assert_eq!(positions, vec![(1, 1), (2, 1), (3, 1), (5, 1)]);
+    }
+
+    #[test]
+    fn test_crlf_trivia() {
+        let tokens: Vec<_> = super::Lexer::new(b"// c\r\n/// d\r\r\x0C\t")
+            .with_trivia()
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Comment, &b"// c"[..]),
+                (TokenKind::Newline, b"\r\n"),
+                (TokenKind::DocComment, b"/// d"),
+                (TokenKind::Newline, b"\r"),
+                (TokenKind::Newline, b"\r"),
+                (TokenKind::Whitespace, b"\x0C\t"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        let input = "\u{FEFF}let x";
+        let spans: Vec<_> = super::Lexer::new(input.as_bytes())
+            .map(|token| (token.kind, token.span()))
+            .collect();
+
+        assert_eq!(
+            spans,
+            vec![
+                (TokenKind::Let, Span::new(3, 6)),
+                (TokenKind::Ident, Span::new(7, 8)),
+            ]
+        );
+
+        assert_eq!(concat_trivia(input.as_bytes()), input.as_bytes());
+        assert_eq!(
+            try_lex("x\u{FEFF}"),
+            vec![
+                Ok(TokenKind::Ident),
+                Err(LexError::UnknownCharacter {
+                    character: '\u{FEFF}',
+                    offset: 1
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_accessors() {
+        let tokens: Vec<_> = super::Lexer::new(b"fn f() -> int {}").collect();
+
+        assert_eq!(tokens[3].kind(), TokenKind::Paren(BracketState::Close));
+        assert_eq!(tokens[4].kind(), TokenKind::Arrow);
+        assert_eq!(tokens[4].text(), b"->");
+        assert_eq!(tokens[4].as_str(), "->");
+        assert_eq!(tokens[4].span(), Span::new(7, 9));
+
+        let token = super::Lexer::new(b"ab\xFF").with_recovery().nth(1).unwrap();
+        assert_eq!(token.kind(), TokenKind::Error);
+        assert_eq!(token.as_str(), "");
+    }
+
+    #[test]
+    fn test_token_kind_names() {
+        assert_eq!(TokenKind::Arrow.to_string(), "`->`");
+        assert_eq!(TokenKind::Ident.to_string(), "identifier");
+        assert_eq!(TokenKind::While.to_string(), "`while`");
+        assert_eq!(TokenKind::Curly(BracketState::Open).to_string(), "`{`");
+        assert_eq!(
+            format!(
+                "expected {}, found {}",
+                TokenKind::Semicolon,
+                TokenKind::Eof
+            ),
+            "expected `;`, found end of file"
+        );
+    }
+
+    #[test]
+    fn test_token_classification() {
+        for (operator, kind) in super::OPERATORS {
+            assert!(kind.is_operator(), "{}", kind);
+            assert!(!kind.is_keyword() && !kind.is_literal(), "{:?}", operator);
+        }
+
+        assert!(TokenKind::While.is_keyword());
+        assert!(!TokenKind::Ident.is_keyword());
+        assert!(TokenKind::True.is_literal() && TokenKind::Char.is_literal());
+        assert!(!TokenKind::StringStart.is_literal());
+        assert!(!TokenKind::Paren(BracketState::Open).is_operator());
+
+        for kind in [
+            TokenKind::Paren(BracketState::Open),
+            TokenKind::Curly(BracketState::Open),
+            TokenKind::Square(BracketState::Open),
+        ] {
+            let close = kind.matching_bracket().unwrap();
+            assert!(kind.is_open_bracket() && !kind.is_close_bracket());
+            assert!(close.is_close_bracket() && !close.is_open_bracket());
+            assert_eq!(close.matching_bracket(), Some(kind));
+        }
+        assert_eq!(TokenKind::Comma.matching_bracket(), None);
+    }
+
+    #[test]
+    fn test_binary_precedence() {
+        let precedence = |kind: TokenKind| kind.binary_precedence().unwrap();
+
+        assert!(precedence(TokenKind::Star) > precedence(TokenKind::Plus));
+        assert_eq!(precedence(TokenKind::Slash), precedence(TokenKind::Star));
+        assert!(precedence(TokenKind::Minus) > precedence(TokenKind::Smaller));
+        assert_eq!(
+            precedence(TokenKind::Bigger),
+            precedence(TokenKind::NotEqual)
+        );
+        assert!(precedence(TokenKind::Equal) > precedence(TokenKind::AndAnd));
+        assert!(precedence(TokenKind::AndAnd) > precedence(TokenKind::OrOr));
+        assert!(precedence(TokenKind::OrOr) > precedence(TokenKind::Assign));
+
+        assert_eq!(TokenKind::Plus.associativity(), Some(Associativity::Left));
+        assert_eq!(
+            TokenKind::Assign.associativity(),
+            Some(Associativity::Right)
+        );
+        assert_eq!(TokenKind::Bang.binary_precedence(), None);
+        assert_eq!(TokenKind::Dot.associativity(), None);
+
+        for (_, kind) in super::OPERATORS {
+            assert_eq!(
+                kind.binary_precedence().is_some(),
+                kind.associativity().is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8_inside_comments_and_literals() {
+        assert_eq!(
+            try_lex_bytes(b"// caf\xE9 let x\nlet y"),
+            vec![
+                Err(LexError::InvalidUtf8 { offset: 6, len: 1 }),
+                Ok(TokenKind::Let),
+                Ok(TokenKind::Ident),
+            ]
+        );
+        assert_eq!(
+            try_lex_bytes(b"/* \xFF \xFE */ x"),
+            vec![
+                Err(LexError::InvalidUtf8 { offset: 3, len: 1 }),
+                Err(LexError::InvalidUtf8 { offset: 5, len: 1 }),
+                Ok(TokenKind::Ident),
+            ]
+        );
+        assert_eq!(
+            try_lex_bytes(b"\"caf\xE9\" + x; let y = 1;"),
+            vec![
+                Err(LexError::InvalidUtf8 { offset: 4, len: 1 }),
+                Ok(TokenKind::Plus),
+                Ok(TokenKind::Ident),
+                Ok(TokenKind::Semicolon),
+                Ok(TokenKind::Let),
+                Ok(TokenKind::Ident),
+                Ok(TokenKind::Assign),
+                Ok(TokenKind::Integer),
+                Ok(TokenKind::Semicolon),
+            ]
+        );
+        assert_eq!(
+            try_lex_bytes(b"r#\"\xFF\"# '\xFF' \"\xFF\\q"),
+            vec![
+                Err(LexError::InvalidUtf8 { offset: 3, len: 1 }),
+                Err(LexError::InvalidUtf8 { offset: 8, len: 1 }),
+                Err(LexError::UnterminatedString { offset: 11 }),
+                Err(LexError::InvalidUtf8 { offset: 12, len: 1 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_inside_literal_recovery() {
+        let input = b"\"a\xFF{x}\xFE\" /* \xFF */ y";
+        let mut lexer = super::Lexer::new(input).with_recovery();
+        let tokens: Vec<_> = lexer
+            .by_ref()
+            .map(|token| (token.kind, token.literal))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Error, &b"\"a\xFF{"[..]),
+                (TokenKind::Ident, b"x"),
+                (TokenKind::Error, b"}\xFE\""),
+                (TokenKind::Error, b"/* \xFF */"),
+                (TokenKind::Ident, b"y"),
+            ]
+        );
+        let spans: Vec<_> = lexer
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.span)
+            .collect();
+        assert_eq!(
+            spans,
+            vec![Span::new(2, 3), Span::new(6, 7), Span::new(12, 13)]
+        );
+        assert_eq!(concat_trivia(input), input);
+    }
+
+    #[test]
+    fn test_unclosed_bracket_positions() {
+        let input = "(\n [ (\r\n\r  {";
+        let positions: Vec<_> = super::Lexer::new(input.as_bytes())
+            .with_recovery()
+            .map(|token| (token.kind, token.line(), token.column()))
+            .collect();
+
+        assert_eq!(
+            positions,
+            vec![
+                (TokenKind::Paren(BracketState::Open), 1, 1),
+                (TokenKind::Square(BracketState::Open), 2, 2),
+                (TokenKind::Paren(BracketState::Open), 2, 4),
+                (TokenKind::Curly(BracketState::Open), 4, 3),
+                (TokenKind::Error, 4, 3),
+                (TokenKind::Error, 2, 4),
+                (TokenKind::Error, 2, 2),
+                (TokenKind::Error, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_many_unclosed_bracket_positions() {
+        let input = "(\n".repeat(10_000);
+        let errors = super::Lexer::new(input.as_bytes())
+            .with_recovery()
+            .filter(|token| token.kind == TokenKind::Error)
+            .inspect(|token| {
+                assert_eq!(
+                    (token.line(), token.column()),
+                    (token.span().start / 2 + 1, 1)
+                )
+            })
+            .count();
+
+        assert_eq!(errors, 10_000);
+    }
 }
@@ -0,0 +1,521 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+
+use crate::{decode_char, LexError, Token, TokenKind};
+
+/// The base of an integer literal, given by its `0b`, `0o` or `0x` prefix.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Radix {
+    pub fn value(self) -> u32 {
+        match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hexadecimal => 16,
+        }
+    }
+}
+
+/// The type suffix of an integer literal, such as the `u8` in `5u8`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IntSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+}
+
+impl IntSuffix {
+    const ALL: [IntSuffix; 12] = [
+        IntSuffix::I8,
+        IntSuffix::I16,
+        IntSuffix::I32,
+        IntSuffix::I64,
+        IntSuffix::I128,
+        IntSuffix::Isize,
+        IntSuffix::U8,
+        IntSuffix::U16,
+        IntSuffix::U32,
+        IntSuffix::U64,
+        IntSuffix::U128,
+        IntSuffix::Usize,
+    ];
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<IntSuffix> {
+        IntSuffix::ALL
+            .into_iter()
+            .find(|suffix| suffix.as_str().as_bytes() == bytes)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IntSuffix::I8 => "i8",
+            IntSuffix::I16 => "i16",
+            IntSuffix::I32 => "i32",
+            IntSuffix::I64 => "i64",
+            IntSuffix::I128 => "i128",
+            IntSuffix::Isize => "isize",
+            IntSuffix::U8 => "u8",
+            IntSuffix::U16 => "u16",
+            IntSuffix::U32 => "u32",
+            IntSuffix::U64 => "u64",
+            IntSuffix::U128 => "u128",
+            IntSuffix::Usize => "usize",
+        }
+    }
+
+    /// The largest value a literal of this type can have.
+    pub fn max_value(self) -> u128 {
+        match self {
+            IntSuffix::I8 => i8::MAX as u128,
+            IntSuffix::I16 => i16::MAX as u128,
+            IntSuffix::I32 => i32::MAX as u128,
+            IntSuffix::I64 => i64::MAX as u128,
+            IntSuffix::I128 => i128::MAX as u128,
+            IntSuffix::Isize => isize::MAX as u128,
+            IntSuffix::U8 => u8::MAX as u128,
+            IntSuffix::U16 => u16::MAX as u128,
+            IntSuffix::U32 => u32::MAX as u128,
+            IntSuffix::U64 => u64::MAX as u128,
+            IntSuffix::U128 => u128::MAX,
+            IntSuffix::Usize => usize::MAX as u128,
+        }
+    }
+
+    /// The largest value a literal of this type can have directly after a `-`. For signed types
+    /// this is one more than `max_value`, as in `-128i8`.
+    pub fn max_negated_value(self) -> u128 {
+        match self {
+            IntSuffix::I8 => i8::MIN.unsigned_abs() as u128,
+            IntSuffix::I16 => i16::MIN.unsigned_abs() as u128,
+            IntSuffix::I32 => i32::MIN.unsigned_abs() as u128,
+            IntSuffix::I64 => i64::MIN.unsigned_abs() as u128,
+            IntSuffix::I128 => i128::MIN.unsigned_abs(),
+            IntSuffix::Isize => isize::MIN.unsigned_abs() as u128,
+            _ => self.max_value(),
+        }
+    }
+}
+
+/// Why an escape sequence in a string or character literal was rejected.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EscapeError {
+    Unknown(char),
+    MalformedHex,
+    HexOutOfRange,
+    MalformedUnicode,
+    InvalidCodepoint,
+}
+
+impl Display for EscapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EscapeError::Unknown(c) => write!(f, "unknown escape `\\{}`", c),
+            EscapeError::MalformedHex => write!(f, "expected two hex digits after `\\x`"),
+            EscapeError::HexOutOfRange => write!(f, "`\\x` escapes must be at most `\\x7F`"),
+            EscapeError::MalformedUnicode => {
+                write!(f, "expected `\\u{{...}}` with 1 to 6 hex digits")
+            }
+            EscapeError::InvalidCodepoint => write!(f, "not a valid unicode scalar value"),
+        }
+    }
+}
+
+/// Decodes the escape sequence at the start of `slice`, which must begin with a backslash
+/// followed by at least one byte. Returns the decoded character and the length of the sequence,
+/// or the error and the length of the malformed sequence.
+pub(crate) fn scan_escape(slice: &[u8]) -> Result<(char, usize), (EscapeError, usize)> {
+    let simple = match slice[1] {
+        b'n' => '\n',
+        b't' => '\t',
+        b'r' => '\r',
+        b'0' => '\0',
+        b'\\' => '\\',
+        b'"' => '"',
+        b'\'' => '\'',
+        b'{' => '{',
+        b'}' => '}',
+        b'x' => return scan_hex_escape(slice),
+        b'u' => return scan_unicode_escape(slice),
+        _ => {
+            let (character, len) = decode_char(&slice[1..]);
+            return Err((EscapeError::Unknown(character), 1 + len));
+        }
+    };
+
+    Ok((simple, 2))
+}
+
+fn scan_hex_escape(slice: &[u8]) -> Result<(char, usize), (EscapeError, usize)> {
+    let digits = slice[2..]
+        .iter()
+        .take(2)
+        .take_while(|byte| byte.is_ascii_hexdigit())
+        .count();
+    if digits < 2 {
+        return Err((EscapeError::MalformedHex, 2 + digits));
+    }
+
+    let value = hex_value(&slice[2..4]);
+    if value > 0x7F {
+        return Err((EscapeError::HexOutOfRange, 4));
+    }
+
+    Ok((value as u8 as char, 4))
+}
+
+fn scan_unicode_escape(slice: &[u8]) -> Result<(char, usize), (EscapeError, usize)> {
+    if slice.get(2) != Some(&b'{') {
+        return Err((EscapeError::MalformedUnicode, 2));
+    }
+
+    let digits = slice[3..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_hexdigit())
+        .count();
+    let end = 3 + digits;
+    if slice.get(end) != Some(&b'}') {
+        return Err((EscapeError::MalformedUnicode, end));
+    }
+    if digits == 0 || digits > 6 {
+        return Err((EscapeError::MalformedUnicode, end + 1));
+    }
+
+    match char::from_u32(hex_value(&slice[3..end])) {
+        Some(character) => Ok((character, end + 1)),
+        None => Err((EscapeError::InvalidCodepoint, end + 1)),
+    }
+}
+
+fn hex_value(digits: &[u8]) -> u32 {
+    digits.iter().fold(0, |value, &digit| {
+        value * 16 + (digit as char).to_digit(16).expect("hex digit")
+    })
+}
+
+/// Replaces the escape sequences in the already validated contents of a literal, borrowing
+/// `contents` when there is nothing to replace.
+pub(crate) fn unescape(contents: &[u8]) -> Cow<'_, str> {
+    if !contents.contains(&b'\\') {
+        return String::from_utf8_lossy(contents);
+    }
+
+    let mut value = String::with_capacity(contents.len());
+    let mut rest = contents;
+    while let Some(backslash) = rest.iter().position(|&byte| byte == b'\\') {
+        value.push_str(&String::from_utf8_lossy(&rest[..backslash]));
+        let (character, len) = scan_escape(&rest[backslash..]).expect("escape was validated");
+        value.push(character);
+        rest = &rest[backslash + len..];
+    }
+    value.push_str(&String::from_utf8_lossy(rest));
+
+    Cow::Owned(value)
+}
+
+impl<'a> Token<'a> {
+    /// The value of a string token, or of the text around an interpolation, with its escapes
+    /// replaced, or the verbatim contents of a raw string token. Borrows from the source unless
+    /// there are escapes to replace.
+    pub fn string_value(&self) -> Option<Cow<'a, str>> {
+        match self.kind {
+            TokenKind::String
+            | TokenKind::StringStart
+            | TokenKind::StringMiddle
+            | TokenKind::StringEnd => Some(unescape(&self.literal[1..self.literal.len() - 1])),
+            TokenKind::RawString => {
+                let hashes = self.literal[1..]
+                    .iter()
+                    .take_while(|&&byte| byte == b'#')
+                    .count();
+                let contents = &self.literal[hashes + 2..self.literal.len() - hashes - 1];
+                Some(String::from_utf8_lossy(contents))
+            }
+            _ => None,
+        }
+    }
+
+    /// The value of a float token, rounded to the nearest `f64`.
+    pub fn float_value(&self) -> Option<f64> {
+        if self.kind != TokenKind::Float {
+            return None;
+        }
+
+        let digits: String = self
+            .literal
+            .iter()
+            .filter(|&&byte| byte != b'_')
+            .map(|&byte| byte as char)
+            .collect();
+        digits.parse().ok()
+    }
+
+    pub fn radix(&self) -> Option<Radix> {
+        match (self.kind, self.literal.get(..2)) {
+            (TokenKind::Integer, Some(b"0x")) => Some(Radix::Hexadecimal),
+            (TokenKind::Integer, Some(b"0o")) => Some(Radix::Octal),
+            (TokenKind::Integer, Some(b"0b")) => Some(Radix::Binary),
+            (TokenKind::Integer, _) => Some(Radix::Decimal),
+            _ => None,
+        }
+    }
+
+    /// The position of the suffix in an integer literal. Suffixes start with `i` or `u`, which
+    /// are not digits in any base.
+    fn suffix_start(&self) -> usize {
+        self.literal
+            .iter()
+            .position(|&byte| byte == b'i' || byte == b'u')
+            .unwrap_or(self.literal.len())
+    }
+
+    pub fn int_suffix(&self) -> Option<IntSuffix> {
+        self.radix()?;
+        IntSuffix::from_bytes(&self.literal[self.suffix_start()..])
+    }
+
+    /// The value of an integer token. Fails when the value does not fit in the type given by the
+    /// suffix, or in `i64` when there is no suffix.
+    pub fn integer_value(&self) -> Option<Result<u128, LexError>> {
+        self.integer_value_up_to(IntSuffix::max_value)
+    }
+
+    /// The value of an integer token written directly after a `-`, which is allowed to be the
+    /// magnitude of its type's minimum.
+    pub fn negated_integer_value(&self) -> Option<Result<u128, LexError>> {
+        self.integer_value_up_to(IntSuffix::max_negated_value)
+    }
+
+    fn integer_value_up_to(&self, max: fn(IntSuffix) -> u128) -> Option<Result<u128, LexError>> {
+        let radix = self.radix()?;
+        let ty = self.int_suffix().unwrap_or(IntSuffix::I64);
+        let overflow = LexError::IntegerOverflow {
+            offset: self.span.start,
+            ty,
+        };
+
+        let prefix = if radix == Radix::Decimal { 0 } else { 2 };
+        let value = self.literal[prefix..self.suffix_start()]
+            .iter()
+            .filter(|&&byte| byte != b'_')
+            .try_fold(0u128, |value, &byte| {
+                let digit = (byte as char).to_digit(radix.value())?;
+                value
+                    .checked_mul(radix.value() as u128)?
+                    .checked_add(digit as u128)
+            });
+
+        Some(match value {
+            Some(value) if value <= max(ty) => Ok(value),
+            _ => Err(overflow),
+        })
+    }
+
+    /// The decoded value of a character literal.
+    pub fn char_value(&self) -> Option<char> {
+        let contents = match self.kind {
+            TokenKind::Char => &self.literal[1..self.literal.len() - 1],
+            _ => return None,
+        };
+
+        match contents[0] {
+            b'\\' => scan_escape(contents).ok().map(|(character, _)| character),
+            _ => Some(decode_char(contents).0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lexer;
+
+    fn string_value(input: &str) -> Cow<'_, str> {
+        Lexer::new(input.as_bytes())
+            .next()
+            .unwrap()
+            .string_value()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_string_value_borrows_without_escapes() {
+        let value = string_value(r#""hello, world""#);
+
+        assert_eq!(value, "hello, world");
+        assert!(matches!(value, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_string_value_unescapes() {
+        let value = string_value(r#""a\n\t\\\"\0\x41\u{1F600}\u{e9}z""#);
+
+        assert_eq!(value, "a\n\t\\\"\0A\u{1F600}\u{e9}z");
+        assert!(matches!(value, Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_string_value_of_other_tokens() {
+        assert_eq!(Lexer::new(b"x").next().unwrap().string_value(), None);
+    }
+
+    #[test]
+    fn test_char_value() {
+        let values: Vec<_> = Lexer::new(r"'a' '\n' '\x41' '\u{1F600}' 'é' '\'' 'label".as_bytes())
+            .map(|token| token.char_value())
+            .collect();
+
+        assert_eq!(
+            values,
+            vec![
+                Some('a'),
+                Some('\n'),
+                Some('A'),
+                Some('\u{1F600}'),
+                Some('é'),
+                Some('\''),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_float_value() {
+        let values: Vec<_> = Lexer::new(b"6.25 1e-9 2.5E+3 0.1 1e400 7")
+            .map(|token| token.float_value())
+            .collect();
+
+        assert_eq!(
+            values,
+            vec![
+                Some(6.25),
+                Some(1e-9),
+                Some(2500.0),
+                Some(0.1),
+                Some(f64::INFINITY),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_integer_radix_and_suffix() {
+        let tokens: Vec<_> = Lexer::new(b"0xFFu8 0o755 0b1010i32 1_000 x")
+            .map(|token| (token.radix(), token.int_suffix()))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (Some(Radix::Hexadecimal), Some(IntSuffix::U8)),
+                (Some(Radix::Octal), None),
+                (Some(Radix::Binary), Some(IntSuffix::I32)),
+                (Some(Radix::Decimal), None),
+                (None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_integer_value() {
+        let values: Vec<_> = Lexer::new(
+            b"0xFF 0o755 0b1010 1_000_000 255u8 256u8 128i8 9223372036854775807 \
+              9223372036854775808 9223372036854775808u64 340282366920938463463374607431768211456u128",
+        )
+        .map(|token| token.integer_value().unwrap())
+        .collect();
+
+        assert_eq!(
+            values,
+            vec![
+                Ok(255),
+                Ok(0o755),
+                Ok(10),
+                Ok(1_000_000),
+                Ok(255),
+                Err(LexError::IntegerOverflow {
+                    offset: 34,
+                    ty: IntSuffix::U8
+                }),
+                Err(LexError::IntegerOverflow {
+                    offset: 40,
+                    ty: IntSuffix::I8
+                }),
+                Ok(i64::MAX as u128),
+                Err(LexError::IntegerOverflow {
+                    offset: 66,
+                    ty: IntSuffix::I64
+                }),
+                Ok(9223372036854775808),
+                Err(LexError::IntegerOverflow {
+                    offset: 109,
+                    ty: IntSuffix::U128
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negated_integer_value() {
+        let values: Vec<_> = Lexer::new(b"128i8 129i8 9223372036854775808 255u8 256u8")
+            .map(|token| token.negated_integer_value().unwrap())
+            .collect();
+
+        assert_eq!(
+            values,
+            vec![
+                Ok(128),
+                Err(LexError::IntegerOverflow {
+                    offset: 6,
+                    ty: IntSuffix::I8
+                }),
+                Ok(9223372036854775808),
+                Ok(255),
+                Err(LexError::IntegerOverflow {
+                    offset: 38,
+                    ty: IntSuffix::U8
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_float_value_with_separators() {
+        let token = Lexer::new(b"1_000.5e1_0").next().unwrap();
+
+        assert_eq!(token.float_value(), Some(1000.5e10));
+    }
+
+    #[test]
+    fn test_raw_string_value() {
+        let value = string_value(r###"r#"C:\path\n "quoted""#"###);
+
+        assert_eq!(value, r#"C:\path\n "quoted""#);
+        assert!(matches!(value, Cow::Borrowed(_)));
+        assert_eq!(string_value(r#"r"""#), "");
+    }
+
+    #[test]
+    fn test_interpolated_string_values() {
+        let values: Vec<_> = Lexer::new(br#""hi {name}, \{literal\} {x}!""#)
+            .filter_map(|token| token.string_value())
+            .collect();
+
+        assert_eq!(values, vec!["hi ", ", {literal} ", "!"]);
+    }
+}
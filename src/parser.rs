@@ -0,0 +1,1079 @@
+use std::fmt::Display;
+
+use crate::{Associativity, BracketState, LexError, Lexer, Span, Token, TokenKind};
+
+/// An expression together with the bytes of source it was parsed from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExprKind {
+    Integer(u128),
+    Float(f64),
+    Bool(bool),
+    Ident(String),
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    /// `target = value`, or a compound assignment such as `target += value` when `op` is set.
+    Assign {
+        op: Option<BinaryOp>,
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    Field {
+        base: Box<Expr>,
+        field: Ident,
+    },
+    Index {
+        base: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Block(Block),
+}
+
+impl Drop for Expr {
+    /// Drops operands from a work list rather than recursively, since chains such as
+    /// `1 + 1 + ... + 1` or `f()()...()` are parsed without a limit on their length.
+    fn drop(&mut self) {
+        let mut operands = Vec::new();
+        let mut kind = std::mem::replace(&mut self.kind, ExprKind::Bool(false));
+
+        loop {
+            match kind {
+                ExprKind::Unary { operand, .. } => operands.push(*operand),
+                ExprKind::Binary { lhs, rhs, .. } => operands.extend([*lhs, *rhs]),
+                ExprKind::Assign { target, value, .. } => operands.extend([*target, *value]),
+                ExprKind::Call { callee, args } => {
+                    operands.push(*callee);
+                    operands.extend(args);
+                }
+                ExprKind::Field { base, .. } => operands.push(*base),
+                ExprKind::Index { base, index } => operands.extend([*base, *index]),
+                _ => {}
+            }
+
+            match operands.pop() {
+                Some(mut operand) => {
+                    kind = std::mem::replace(&mut operand.kind, ExprKind::Bool(false));
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// Statements in braces, optionally ending in an expression without a `;` that gives the block
+/// its value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub expr: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Stmt {
+    Let(Let),
+    /// An expression followed by `;`, or a block expression in statement position.
+    Expr(Expr),
+    Item(Item),
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Let(binding) => binding.span,
+            Stmt::Expr(expr) => expr.span,
+            Stmt::Item(item) => item.span(),
+        }
+    }
+}
+
+/// `let [mut] name [: Type] = value;`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Let {
+    pub mutable: bool,
+    pub name: Ident,
+    pub ty: Option<Ident>,
+    pub value: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Item {
+    Fn(FnDecl),
+}
+
+impl Item {
+    pub fn span(&self) -> Span {
+        match self {
+            Item::Fn(decl) => decl.span,
+        }
+    }
+}
+
+/// `fn name(params) [-> Type] { body }`. Types are plain names such as `int`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FnDecl {
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub ret: Option<Ident>,
+    pub body: Block,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: Ident,
+    pub span: Span,
+}
+
+/// A name and where it was written.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+impl BinaryOp {
+    fn from_kind(kind: TokenKind) -> Option<BinaryOp> {
+        let op = match kind {
+            TokenKind::Plus | TokenKind::PlusAssign => BinaryOp::Add,
+            TokenKind::Minus | TokenKind::MinusAssign => BinaryOp::Sub,
+            TokenKind::Star | TokenKind::StarAssign => BinaryOp::Mul,
+            TokenKind::Slash | TokenKind::SlashAssign => BinaryOp::Div,
+            TokenKind::Percent => BinaryOp::Rem,
+            TokenKind::Smaller => BinaryOp::Lt,
+            TokenKind::Bigger => BinaryOp::Gt,
+            TokenKind::SmallerEqual => BinaryOp::Le,
+            TokenKind::BiggerEqual => BinaryOp::Ge,
+            TokenKind::Equal => BinaryOp::Eq,
+            TokenKind::NotEqual => BinaryOp::Ne,
+            TokenKind::AndAnd => BinaryOp::And,
+            TokenKind::OrOr => BinaryOp::Or,
+            TokenKind::Ampersand => BinaryOp::BitAnd,
+            TokenKind::Pipe => BinaryOp::BitOr,
+            TokenKind::Caret => BinaryOp::BitXor,
+            TokenKind::ShiftLeft => BinaryOp::Shl,
+            TokenKind::ShiftRight => BinaryOp::Shr,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ParseError {
+    Lex(LexError),
+    /// `expected` describes what the parser was looking for, such as "`)`" or "expression".
+    Unexpected {
+        expected: &'static str,
+        found: TokenKind,
+        span: Span,
+    },
+    /// Brackets, blocks or operands nested more than `MAX_NESTING` levels deep. `span` is the
+    /// token that would have gone one level deeper.
+    TooDeep {
+        span: Span,
+    },
+}
+
+impl ParseError {
+    pub fn offset(&self) -> usize {
+        match self {
+            ParseError::Lex(error) => error.offset(),
+            ParseError::Unexpected { span, .. } | ParseError::TooDeep { span } => span.start,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseError::Lex(error) => error.fmt(f),
+            ParseError::Unexpected {
+                expected,
+                found,
+                span,
+            } => write!(
+                f,
+                "Expected {}, found {} at byte {}",
+                expected, found, span.start
+            ),
+            ParseError::TooDeep { span } => write!(
+                f,
+                "Nested more than {} levels deep at byte {}",
+                MAX_NESTING, span.start
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<LexError> for ParseError {
+    fn from(error: LexError) -> Self {
+        ParseError::Lex(error)
+    }
+}
+
+/// Binds tighter than every binary operator, so `-a * b` is `(-a) * b`.
+const UNARY_PRECEDENCE: u8 = u8::MAX;
+
+/// How deeply blocks and operands, including those in parentheses, arguments and indices, may
+/// nest before parsing fails with `ParseError::TooDeep`. Parsing this deep fits in a 1 MiB
+/// stack, the smallest main thread stack of common platforms, even in debug builds.
+pub const MAX_NESTING: usize = 64;
+
+/// A parser over the tokens of a `Lexer`, with one token of lookahead. Lexing errors stop the
+/// parser like any other error.
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<Token<'a>>,
+    /// How many blocks and operands are being parsed inside each other.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Parser<'a> {
+        Parser {
+            lexer,
+            peeked: None,
+            depth: 0,
+        }
+    }
+
+    /// The next token without consuming it. Past the last token this is an empty `Eof` token
+    /// at the end of the source.
+    fn peek(&mut self) -> Result<Token<'a>, ParseError> {
+        if let Some(token) = self.peeked {
+            return Ok(token);
+        }
+
+        let token = match self.lexer.next_token() {
+            Some(token) => token?,
+            None => {
+                let end = self.lexer.source.len();
+                let mut token = Token::new(TokenKind::Eof, b"");
+                token.span = Span::in_file(self.lexer.file, end, end);
+                token
+            }
+        };
+        self.peeked = Some(token);
+        Ok(token)
+    }
+
+    fn bump(&mut self) -> Result<Token<'a>, ParseError> {
+        let token = self.peek()?;
+        self.peeked = None;
+        Ok(token)
+    }
+
+    /// Consumes the next token if it is of `kind`.
+    fn eat(&mut self, kind: TokenKind) -> Result<Option<Token<'a>>, ParseError> {
+        if self.peek()?.kind == kind {
+            return self.bump().map(Some);
+        }
+        Ok(None)
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<Token<'a>, ParseError> {
+        match self.eat(kind)? {
+            Some(token) => Ok(token),
+            None => self.unexpected(kind.as_str()),
+        }
+    }
+
+    /// Fails with the next token as the one found instead of `expected`.
+    fn unexpected<T>(&mut self, expected: &'static str) -> Result<T, ParseError> {
+        let found = self.peek()?;
+        Err(ParseError::Unexpected {
+            expected,
+            found: found.kind,
+            span: found.span,
+        })
+    }
+
+    /// Fails unless every token has been consumed.
+    pub fn finish(&mut self) -> Result<(), ParseError> {
+        match self.peek()?.kind {
+            TokenKind::Eof => Ok(()),
+            _ => self.unexpected("end of file"),
+        }
+    }
+
+    /// Goes one level of nesting deeper at `span`, failing instead of overflowing the host stack
+    /// past `MAX_NESTING`. The caller leaves the level again by decrementing `depth`.
+    fn enter(&mut self, span: Span) -> Result<(), ParseError> {
+        if self.depth >= MAX_NESTING {
+            return Err(ParseError::TooDeep { span });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<Ident, ParseError> {
+        let token = self.expect(TokenKind::Ident)?;
+        Ok(Ident {
+            name: token.as_str().to_string(),
+            span: token.span,
+        })
+    }
+
+    /// Parses statements up to the end of the source, as if they were the body of a block.
+    pub fn parse_program(&mut self) -> Result<Block, ParseError> {
+        let (stmts, expr) = self.block_body(TokenKind::Eof)?;
+        let end = self.peek()?.span;
+        Ok(Block {
+            stmts,
+            expr,
+            span: Span::in_file(end.file, 0, end.end),
+        })
+    }
+
+    pub fn parse_block(&mut self) -> Result<Block, ParseError> {
+        let open = self.expect(TokenKind::Curly(BracketState::Open))?;
+        self.enter(open.span)?;
+        let body = self.block_body(TokenKind::Curly(BracketState::Close));
+        self.depth -= 1;
+        let (stmts, expr) = body?;
+        let close = self.expect(TokenKind::Curly(BracketState::Close))?;
+        Ok(Block {
+            stmts,
+            expr,
+            span: open.span.to(close.span),
+        })
+    }
+
+    /// Parses statements up to, but not including, `end`. An expression directly before `end`
+    /// without a `;` becomes the value of the block.
+    fn block_body(&mut self, end: TokenKind) -> Result<(Vec<Stmt>, Option<Box<Expr>>), ParseError> {
+        let mut stmts = Vec::new();
+
+        loop {
+            let kind = self.peek()?.kind;
+            if kind == end {
+                return Ok((stmts, None));
+            }
+
+            match kind {
+                TokenKind::Let => stmts.push(Stmt::Let(self.parse_let()?)),
+                TokenKind::Fn => stmts.push(Stmt::Item(self.parse_item()?)),
+                TokenKind::Semicolon => {
+                    self.bump()?;
+                }
+                _ => {
+                    let expr = self.parse_expr()?;
+                    if self.eat(TokenKind::Semicolon)?.is_some() {
+                        stmts.push(Stmt::Expr(expr));
+                    } else if self.peek()?.kind == end {
+                        return Ok((stmts, Some(Box::new(expr))));
+                    } else if matches!(expr.kind, ExprKind::Block(_)) {
+                        stmts.push(Stmt::Expr(expr));
+                    } else {
+                        return self.unexpected(TokenKind::Semicolon.as_str());
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn parse_let(&mut self) -> Result<Let, ParseError> {
+        let start = self.expect(TokenKind::Let)?.span;
+        let mutable = self.eat(TokenKind::Mut)?.is_some();
+        let name = self.ident()?;
+        let ty = match self.eat(TokenKind::Colon)? {
+            Some(_) => Some(self.ident()?),
+            None => None,
+        };
+        self.expect(TokenKind::Assign)?;
+        let value = self.parse_expr()?;
+        let end = self.expect(TokenKind::Semicolon)?.span;
+
+        Ok(Let {
+            mutable,
+            name,
+            ty,
+            value,
+            span: start.to(end),
+        })
+    }
+
+    pub fn parse_item(&mut self) -> Result<Item, ParseError> {
+        match self.peek()?.kind {
+            TokenKind::Fn => Ok(Item::Fn(self.parse_fn()?)),
+            _ => self.unexpected("item"),
+        }
+    }
+
+    fn parse_fn(&mut self) -> Result<FnDecl, ParseError> {
+        let start = self.expect(TokenKind::Fn)?.span;
+        let name = self.ident()?;
+
+        self.expect(TokenKind::Paren(BracketState::Open))?;
+        let mut params = Vec::new();
+        while self.peek()?.kind != TokenKind::Paren(BracketState::Close) {
+            let name = self.ident()?;
+            self.expect(TokenKind::Colon)?;
+            let ty = self.ident()?;
+            params.push(Param {
+                span: name.span.to(ty.span),
+                name,
+                ty,
+            });
+            if self.eat(TokenKind::Comma)?.is_none() {
+                break;
+            }
+        }
+        self.expect(TokenKind::Paren(BracketState::Close))?;
+
+        let ret = match self.eat(TokenKind::Arrow)? {
+            Some(_) => Some(self.ident()?),
+            None => None,
+        };
+        let body = self.parse_block()?;
+
+        Ok(FnDecl {
+            span: start.to(body.span),
+            name,
+            params,
+            ret,
+            body,
+        })
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.expr_with_precedence(0)
+    }
+
+    /// Parses an expression whose binary operators all bind at least as tightly as `min`.
+    fn expr_with_precedence(&mut self, min: u8) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+
+        loop {
+            let kind = self.peek()?.kind;
+            let precedence = match kind.binary_precedence() {
+                Some(precedence) if precedence >= min => precedence,
+                _ => return Ok(lhs),
+            };
+            let operator = self.bump()?;
+
+            let next_min = match kind.associativity() {
+                Some(Associativity::Right) => precedence,
+                _ => precedence + 1,
+            };
+            // Right operands of right-associative operators nest inside each other.
+            self.enter(operator.span)?;
+            let rhs = self.expr_with_precedence(next_min);
+            self.depth -= 1;
+            let rhs = rhs?;
+            let span = lhs.span.to(rhs.span);
+
+            let kind = match kind {
+                TokenKind::Assign
+                | TokenKind::PlusAssign
+                | TokenKind::MinusAssign
+                | TokenKind::StarAssign
+                | TokenKind::SlashAssign => ExprKind::Assign {
+                    op: BinaryOp::from_kind(kind),
+                    target: Box::new(lhs),
+                    value: Box::new(rhs),
+                },
+                _ => ExprKind::Binary {
+                    op: BinaryOp::from_kind(kind).expect("every binary operator has a BinaryOp"),
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+            };
+            lhs = Expr { kind, span };
+        }
+    }
+
+    /// Every recursion through expressions passes through here, so this is where nesting is
+    /// counted.
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let span = self.peek()?.span;
+        self.enter(span)?;
+        let expr = self.prefix();
+        self.depth -= 1;
+        expr
+    }
+
+    fn prefix(&mut self) -> Result<Expr, ParseError> {
+        let op = match self.peek()?.kind {
+            TokenKind::Minus => UnaryOp::Neg,
+            TokenKind::Bang => UnaryOp::Not,
+            _ => return self.postfix(),
+        };
+
+        let token = self.bump()?;
+        let operand = match (op, self.peek()?.kind) {
+            (UnaryOp::Neg, TokenKind::Integer) => self.negated_integer()?,
+            _ => self.expr_with_precedence(UNARY_PRECEDENCE)?,
+        };
+        Ok(Expr {
+            span: token.span.to(operand.span),
+            kind: ExprKind::Unary {
+                op,
+                operand: Box::new(operand),
+            },
+        })
+    }
+
+    /// Parses the integer literal after a `-`, which may be the magnitude of its type's minimum,
+    /// as in `-9223372036854775808`, unless a call, field access or indexing applies to it first.
+    fn negated_integer(&mut self) -> Result<Expr, ParseError> {
+        let token = self.bump()?;
+        let value = match self.peek()?.kind {
+            TokenKind::Paren(BracketState::Open)
+            | TokenKind::Dot
+            | TokenKind::Square(BracketState::Open) => token.integer_value(),
+            _ => token.negated_integer_value(),
+        };
+
+        let literal = Expr {
+            kind: ExprKind::Integer(value.expect("integer tokens have a value")?),
+            span: token.span,
+        };
+        self.postfix_of(literal)
+    }
+
+    /// Parses a primary expression followed by any calls, field accesses and indexing.
+    fn postfix(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.primary()?;
+        self.postfix_of(expr)
+    }
+
+    fn postfix_of(&mut self, mut expr: Expr) -> Result<Expr, ParseError> {
+        loop {
+            let start = expr.span;
+            let (kind, end) = match self.peek()?.kind {
+                TokenKind::Paren(BracketState::Open) => {
+                    self.bump()?;
+                    let args = self.call_args()?;
+                    let close = self.expect(TokenKind::Paren(BracketState::Close))?;
+                    let callee = Box::new(expr);
+                    (ExprKind::Call { callee, args }, close.span)
+                }
+                TokenKind::Dot => {
+                    self.bump()?;
+                    let field = self.ident()?;
+                    let end = field.span;
+                    let base = Box::new(expr);
+                    (ExprKind::Field { base, field }, end)
+                }
+                TokenKind::Square(BracketState::Open) => {
+                    self.bump()?;
+                    let index = Box::new(self.parse_expr()?);
+                    let close = self.expect(TokenKind::Square(BracketState::Close))?;
+                    let base = Box::new(expr);
+                    (ExprKind::Index { base, index }, close.span)
+                }
+                _ => return Ok(expr),
+            };
+
+            expr = Expr {
+                kind,
+                span: start.to(end),
+            };
+        }
+    }
+
+    /// Parses comma-separated arguments up to, but not including, the closing `)`. A trailing
+    /// comma is allowed.
+    fn call_args(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut args = Vec::new();
+        while self.peek()?.kind != TokenKind::Paren(BracketState::Close) {
+            args.push(self.parse_expr()?);
+            if self.eat(TokenKind::Comma)?.is_none() {
+                break;
+            }
+        }
+        Ok(args)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let token = self.peek()?;
+
+        let kind = match token.kind {
+            TokenKind::Integer => ExprKind::Integer(
+                token
+                    .integer_value()
+                    .expect("integer tokens have a value")?,
+            ),
+            TokenKind::Float => {
+                ExprKind::Float(token.float_value().expect("float tokens have a value"))
+            }
+            TokenKind::True => ExprKind::Bool(true),
+            TokenKind::False => ExprKind::Bool(false),
+            TokenKind::Ident => ExprKind::Ident(token.as_str().to_string()),
+            TokenKind::Curly(BracketState::Open) => {
+                let block = self.parse_block()?;
+                return Ok(Expr {
+                    span: block.span,
+                    kind: ExprKind::Block(block),
+                });
+            }
+            TokenKind::Paren(BracketState::Open) => {
+                self.bump()?;
+                let mut inner = self.parse_expr()?;
+                let close = self.expect(TokenKind::Paren(BracketState::Close))?;
+                inner.span = token.span.to(close.span);
+                return Ok(inner);
+            }
+            _ => return self.unexpected("expression"),
+        };
+
+        self.bump()?;
+        Ok(Expr {
+            kind,
+            span: token.span,
+        })
+    }
+}
+
+/// Parses `source` as a single expression.
+pub fn parse_expr(source: &[u8]) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let expr = parser.parse_expr()?;
+    parser.finish()?;
+    Ok(expr)
+}
+
+/// Parses `source` as a sequence of statements and items.
+pub fn parse_program(source: &[u8]) -> Result<Block, ParseError> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program()?;
+    parser.finish()?;
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prints `expr` as an s-expression, so tests can check its shape at a glance.
+    fn sexp(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Integer(value) => value.to_string(),
+            ExprKind::Float(value) => value.to_string(),
+            ExprKind::Bool(value) => value.to_string(),
+            ExprKind::Ident(name) => name.clone(),
+            ExprKind::Unary { op, operand } => {
+                let op = match op {
+                    UnaryOp::Neg => "neg",
+                    UnaryOp::Not => "not",
+                };
+                format!("({} {})", op, sexp(operand))
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                format!("({} {} {})", op.as_str(), sexp(lhs), sexp(rhs))
+            }
+            ExprKind::Assign { op, target, value } => format!(
+                "({}= {} {})",
+                op.map_or("", BinaryOp::as_str),
+                sexp(target),
+                sexp(value)
+            ),
+            ExprKind::Call { callee, args } => {
+                let args: Vec<_> = args.iter().map(sexp).collect();
+                format!("(call {} [{}])", sexp(callee), args.join(" "))
+            }
+            ExprKind::Field { base, field } => format!("(. {} {})", sexp(base), field.name),
+            ExprKind::Index { base, index } => format!("(index {} {})", sexp(base), sexp(index)),
+            ExprKind::Block(block) => block_sexp(block),
+        }
+    }
+
+    fn block_sexp(block: &Block) -> String {
+        let mut parts: Vec<_> = block.stmts.iter().map(stmt_sexp).collect();
+        if let Some(expr) = &block.expr {
+            parts.push(sexp(expr));
+        }
+        format!("{{{}}}", parts.join(" "))
+    }
+
+    fn stmt_sexp(stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Let(binding) => format!(
+                "(let{} {}{} {})",
+                if binding.mutable { " mut" } else { "" },
+                binding.name.name,
+                binding
+                    .ty
+                    .as_ref()
+                    .map_or(String::new(), |ty| format!(": {}", ty.name)),
+                sexp(&binding.value)
+            ),
+            Stmt::Expr(expr) => format!("{};", sexp(expr)),
+            Stmt::Item(Item::Fn(decl)) => {
+                let params: Vec<_> = decl
+                    .params
+                    .iter()
+                    .map(|param| format!("{}: {}", param.name.name, param.ty.name))
+                    .collect();
+                format!(
+                    "(fn {}({}){} {})",
+                    decl.name.name,
+                    params.join(", "),
+                    decl.ret
+                        .as_ref()
+                        .map_or(String::new(), |ty| format!(" -> {}", ty.name)),
+                    block_sexp(&decl.body)
+                )
+            }
+        }
+    }
+
+    fn program(source: &str) -> String {
+        block_sexp(&parse_program(source.as_bytes()).unwrap())
+    }
+
+    fn parse(source: &str) -> String {
+        sexp(&parse_expr(source.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(parse("1 + 2 * 3 - 4"), "(- (+ 1 (* 2 3)) 4)");
+        assert_eq!(parse("a / b / c"), "(/ (/ a b) c)");
+        assert_eq!(parse("a + 1 < b * 2"), "(< (+ a 1) (* b 2))");
+        assert_eq!(parse("(1 + 2) * 3"), "(* (+ 1 2) 3)");
+        assert_eq!(parse("a > 0 && b < 1 || c"), "(|| (&& (> a 0) (< b 1)) c)");
+        assert_eq!(parse("x = y += 2 * 1.5"), "(= x (+= y (* 2 1.5)))");
+    }
+
+    #[test]
+    fn test_unary() {
+        assert_eq!(parse("-a * -b"), "(* (neg a) (neg b))");
+        assert_eq!(parse("--1"), "(neg (neg 1))");
+        assert_eq!(parse("!true"), "(not true)");
+        assert_eq!(parse("-f(x).y"), "(neg (. (call f [x]) y))");
+        assert_eq!(
+            parse("-9223372036854775808 - 1"),
+            "(- (neg 9223372036854775808) 1)"
+        );
+        assert_eq!(parse("-128i8"), "(neg 128)");
+        assert!(matches!(
+            parse_expr(b"-9223372036854775808.x"),
+            Err(ParseError::Lex(LexError::IntegerOverflow { offset: 1, .. }))
+        ));
+        assert!(matches!(
+            parse_expr(b"-(9223372036854775808)"),
+            Err(ParseError::Lex(LexError::IntegerOverflow { offset: 2, .. }))
+        ));
+        assert!(matches!(
+            parse_expr(b"-9223372036854775809"),
+            Err(ParseError::Lex(LexError::IntegerOverflow { offset: 1, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_postfix() {
+        assert_eq!(parse("f()"), "(call f [])");
+        assert_eq!(parse("f(a, b + 1,)"), "(call f [a (+ b 1)])");
+        assert_eq!(parse("a.b[i + 1](c)"), "(call (index (. a b) (+ i 1)) [c])");
+        assert_eq!(parse("f(g(x))[0]"), "(index (call f [(call g [x])]) 0)");
+    }
+
+    #[test]
+    fn test_spans() {
+        let expr = parse_expr(b"(a + b) * f(1, 2)").unwrap();
+        assert_eq!(expr.span, Span::new(0, 17));
+
+        let ExprKind::Binary { lhs, rhs, .. } = &expr.kind else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(lhs.span, Span::new(0, 7));
+        assert_eq!(rhs.span, Span::new(10, 17));
+
+        let ExprKind::Call { args, .. } = &rhs.kind else {
+            panic!("expected a call");
+        };
+        assert_eq!(args[1].span, Span::new(15, 16));
+
+        let expr = parse_expr(b"-x.y").unwrap();
+        assert_eq!(expr.span, Span::new(0, 4));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse_expr(b"1 +"),
+            Err(ParseError::Unexpected {
+                expected: "expression",
+                found: TokenKind::Eof,
+                span: Span::new(3, 3),
+            })
+        );
+        assert_eq!(
+            parse_expr(b"f(1 2)").unwrap_err().to_string(),
+            "Expected `)`, found integer literal at byte 4"
+        );
+        assert_eq!(
+            parse_expr(b"a b").unwrap_err().to_string(),
+            "Expected end of file, found identifier at byte 2"
+        );
+        assert_eq!(
+            parse_expr(b"a.1").unwrap_err().to_string(),
+            "Expected identifier, found integer literal at byte 2"
+        );
+        assert_eq!(
+            parse_expr(b"1 + $"),
+            Err(ParseError::Lex(LexError::UnknownCharacter {
+                character: '$',
+                offset: 4,
+            }))
+        );
+        assert!(matches!(
+            parse_expr(b"99999999999999999999"),
+            Err(ParseError::Lex(LexError::IntegerOverflow { offset: 0, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(program("let mut five = 5;"), "{(let mut five 5)}");
+        assert_eq!(
+            program("let x: int = 1 + 2; let y = x;"),
+            "{(let x: int (+ 1 2)) (let y x)}"
+        );
+    }
+
+    #[test]
+    fn test_fn() {
+        assert_eq!(
+            program("fn add(x: int, y: int) -> int { x + y }"),
+            "{(fn add(x: int, y: int) -> int {(+ x y)})}"
+        );
+        assert_eq!(
+            program("fn noop() {} noop()"),
+            "{(fn noop() {}) (call noop [])}"
+        );
+        assert_eq!(
+            program("fn f(a: int,) { fn g() -> int { 1 } g(); }"),
+            "{(fn f(a: int) {(fn g() -> int {1}) (call g []);})}"
+        );
+    }
+
+    #[test]
+    fn test_block_values() {
+        assert_eq!(program("1; 2"), "{1; 2}");
+        assert_eq!(program("1; 2;"), "{1; 2;}");
+        assert_eq!(
+            program("let x = { let y = 1; y * 2 }; { x } x"),
+            "{(let x {(let y 1) (* y 2)}) {x}; x}"
+        );
+        assert_eq!(program(";;"), "{}");
+        assert_eq!(program("{ 1 }"), "{{1}}");
+        assert_eq!(program("let y = 1; { 2 }"), "{(let y 1) {2}}");
+        assert_eq!(
+            program("fn f() -> int { { 1 } }"),
+            "{(fn f() -> int {{1}})}"
+        );
+        assert!(parse_program(b"{ 1 }").unwrap().expr.is_some());
+    }
+
+    #[test]
+    fn test_statement_spans() {
+        let source = b"let mut five = 5;\nfn add(x: int, y: int) -> int {\n    x + y\n}\n";
+        let program = parse_program(source).unwrap();
+
+        assert_eq!(program.span, Span::new(0, source.len()));
+        assert_eq!(program.stmts[0].span(), Span::new(0, 17));
+
+        let Stmt::Let(binding) = &program.stmts[0] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(binding.name.span, Span::new(8, 12));
+        assert_eq!(binding.value.span, Span::new(15, 16));
+
+        let Stmt::Item(Item::Fn(decl)) = &program.stmts[1] else {
+            panic!("expected a function");
+        };
+        assert_eq!(decl.span, Span::new(18, 61));
+        assert_eq!(decl.params[1].span, Span::new(33, 39));
+        assert_eq!(decl.ret.as_ref().unwrap().span, Span::new(44, 47));
+        assert_eq!(decl.body.span, Span::new(48, 61));
+        assert_eq!(decl.body.expr.as_ref().unwrap().span, Span::new(54, 59));
+    }
+
+    #[test]
+    fn test_statement_errors() {
+        let error = |source: &str| parse_program(source.as_bytes()).unwrap_err().to_string();
+
+        assert_eq!(
+            error("let x 1;"),
+            "Expected `=`, found integer literal at byte 6"
+        );
+        assert_eq!(
+            error("let x = 1"),
+            "Expected `;`, found end of file at byte 9"
+        );
+        assert_eq!(
+            error("1 2"),
+            "Expected `;`, found integer literal at byte 2"
+        );
+        assert_eq!(error("fn f(x) {}"), "Expected `:`, found `)` at byte 6");
+        assert_eq!(
+            error("fn f() -> { 1 }"),
+            "Expected identifier, found `{` at byte 10"
+        );
+        assert_eq!(
+            error("let = 1;"),
+            "Expected identifier, found `=` at byte 4"
+        );
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |open: &str, close: &str, depth: usize| {
+            format!("{}1{}", open.repeat(depth), close.repeat(depth))
+        };
+
+        assert!(parse_expr(nested("(", ")", MAX_NESTING - 1).as_bytes()).is_ok());
+        assert_eq!(
+            parse_expr(nested("(", ")", MAX_NESTING).as_bytes()),
+            Err(ParseError::TooDeep {
+                span: Span::new(MAX_NESTING, MAX_NESTING + 1),
+            })
+        );
+        assert_eq!(
+            parse_expr("(".repeat(100_000).as_bytes())
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "Nested more than {} levels deep at byte {}",
+                MAX_NESTING, MAX_NESTING
+            )
+        );
+        assert!(matches!(
+            parse_expr("-{".repeat(100_000).as_bytes()),
+            Err(ParseError::TooDeep { .. })
+        ));
+        assert!(matches!(
+            parse_program("fn f() {".repeat(100_000).as_bytes()),
+            Err(ParseError::TooDeep { .. })
+        ));
+        assert!(matches!(
+            parse_expr(format!("{}1", "a = ".repeat(100_000)).as_bytes()),
+            Err(ParseError::TooDeep { .. })
+        ));
+    }
+
+    #[test]
+    fn test_long_chains_drop_without_overflow() {
+        let sum = format!("{}1", "1 + ".repeat(200_000));
+        assert!(matches!(
+            parse_expr(sum.as_bytes()).unwrap().kind,
+            ExprKind::Binary {
+                op: BinaryOp::Add,
+                ..
+            }
+        ));
+
+        let calls = format!("f{}", "()".repeat(200_000));
+        assert!(parse_expr(calls.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_nesting_limit_on_a_small_stack() {
+        let shapes = [
+            ("(", ")"),
+            ("{", "}"),
+            ("-{", "}"),
+            ("f(", ")"),
+            ("a[", "]"),
+            ("a = ", ""),
+            ("fn f() {", "}"),
+        ];
+
+        for (open, close) in shapes {
+            let deepest = std::thread::Builder::new()
+                .stack_size(1024 * 1024)
+                .spawn(move || {
+                    let mut depth = 0;
+                    loop {
+                        let source =
+                            format!("{}1{}", open.repeat(depth + 1), close.repeat(depth + 1));
+                        match parse_program(source.as_bytes()) {
+                            Ok(_) => depth += 1,
+                            Err(ParseError::TooDeep { .. }) => return depth,
+                            Err(error) => panic!("unexpected error {}", error),
+                        }
+                    }
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+
+            assert!(deepest > 0);
+        }
+    }
+}
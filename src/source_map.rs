@@ -0,0 +1,194 @@
+use crate::{Lexer, Span};
+
+/// Identifies a file added to a `SourceMap`.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct FileId(u32);
+
+impl FileId {
+    /// The file of spans produced by a `Lexer` that was not created through a `SourceMap`.
+    pub const ANONYMOUS: FileId = FileId(0);
+}
+
+struct SourceFile {
+    name: String,
+    source: String,
+    line_starts: Vec<usize>,
+}
+
+/// A resolved position inside a file of a `SourceMap`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Location<'a> {
+    pub file: FileId,
+    pub name: &'a str,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, in bytes.
+    pub column: usize,
+    /// The text of the line, without its line terminator.
+    pub line_text: &'a str,
+}
+
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        let source = source.into();
+        let line_starts = std::iter::once(0)
+            .chain(
+                (0..source.len())
+                    .filter(|&index| crate::is_line_break(source.as_bytes(), index))
+                    .map(|index| index + 1),
+            )
+            .collect();
+
+        self.files.push(SourceFile {
+            name: name.into(),
+            source,
+            line_starts,
+        });
+
+        FileId(self.files.len() as u32)
+    }
+
+    fn file(&self, file: FileId) -> Option<&SourceFile> {
+        let index = (file.0 as usize).checked_sub(1)?;
+        self.files.get(index)
+    }
+
+    pub fn name(&self, file: FileId) -> Option<&str> {
+        self.file(file).map(|file| file.name.as_str())
+    }
+
+    pub fn source(&self, file: FileId) -> Option<&str> {
+        self.file(file).map(|file| file.source.as_str())
+    }
+
+    /// Creates a lexer over `file` whose spans are tagged with its `FileId`.
+    pub fn lexer(&self, file: FileId) -> Option<Lexer<'_>> {
+        let source = self.source(file)?;
+        let mut lexer = Lexer::new(source.as_bytes());
+        lexer.file = file;
+        Some(lexer)
+    }
+
+    /// Resolves the start of `span` to a location in its file.
+    pub fn resolve(&self, span: Span) -> Option<Location<'_>> {
+        self.locate(span.file, span.start)
+    }
+
+    pub fn locate(&self, file: FileId, offset: usize) -> Option<Location<'_>> {
+        let source_file = self.file(file)?;
+        if offset > source_file.source.len() {
+            return None;
+        }
+
+        let line = source_file
+            .line_starts
+            .partition_point(|&start| start <= offset);
+        let line_start = source_file.line_starts[line - 1];
+        let line_end = source_file
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(source_file.source.len());
+
+        Some(Location {
+            file,
+            name: &source_file.name,
+            line,
+            column: offset - line_start + 1,
+            line_text: source_file.source[line_start..line_end].trim_end_matches(['\n', '\r']),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_across_files() {
+        let mut map = SourceMap::new();
+        let main = map.add("main.lx", "let x = 1;\nlet y = x;\n");
+        let lib = map.add("lib.lx", "fn id(x: int) -> int {\n    x\n}");
+
+        assert_ne!(main, lib);
+
+        let tokens: Vec<_> = map.lexer(lib).unwrap().collect();
+        let x = tokens[tokens.len() - 2];
+
+        assert_eq!(x.span(), Span::in_file(lib, 27, 28));
+        assert_eq!(
+            map.resolve(x.span()),
+            Some(Location {
+                file: lib,
+                name: "lib.lx",
+                line: 2,
+                column: 5,
+                line_text: "    x",
+            })
+        );
+
+        let y = map.lexer(main).unwrap().nth(6).unwrap();
+        assert_eq!(
+            map.resolve(y.span()),
+            Some(Location {
+                file: main,
+                name: "main.lx",
+                line: 2,
+                column: 5,
+                line_text: "let y = x;",
+            })
+        );
+    }
+
+    #[test]
+    fn test_resolve_end_of_file() {
+        let mut map = SourceMap::new();
+        let file = map.add("empty.lx", "a\n");
+
+        let location = map.locate(file, 2).unwrap();
+        assert_eq!(
+            (location.line, location.column, location.line_text),
+            (2, 1, "")
+        );
+        assert_eq!(map.locate(file, 3), None);
+    }
+
+    #[test]
+    fn test_resolve_crlf() {
+        let mut map = SourceMap::new();
+        let file = map.add("crlf.lx", "let x = 1;\r\nx\ry\r\n");
+
+        let lines: Vec<_> = map
+            .lexer(file)
+            .unwrap()
+            .map(|token| {
+                let location = map.resolve(token.span()).unwrap();
+                (location.line, location.column, location.line_text)
+            })
+            .collect();
+
+        assert_eq!(lines[lines.len() - 2..], [(2, 1, "x"), (3, 1, "y")]);
+        assert_eq!(lines[0], (1, 1, "let x = 1;"));
+        assert_eq!(map.locate(file, 17).unwrap().line, 4);
+    }
+
+    #[test]
+    fn test_anonymous_spans_do_not_resolve() {
+        let mut map = SourceMap::new();
+        map.add("main.lx", "let x = 1;");
+
+        let token = Lexer::new(b"let x = 1;").next().unwrap();
+        assert_eq!(token.span().file, FileId::ANONYMOUS);
+        assert_eq!(map.resolve(token.span()), None);
+        assert!(map.lexer(FileId::ANONYMOUS).is_none());
+    }
+}